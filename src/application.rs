@@ -1,12 +1,25 @@
 use crate::prelude::*;
 
-use std::sync::Arc;
+use std::{
+    borrow::Cow,
+    collections::HashMap,
+    io::Cursor,
+    sync::{Arc, Mutex},
+};
 
 use invoker_auth::{Cert, Challenge, policy};
-use tokio::{sync::mpsc::unbounded_channel, task::JoinHandle};
+use tokio::{
+    io::AsyncRead,
+    sync::mpsc::unbounded_channel,
+    task::{AbortHandle, JoinHandle},
+};
 
 use crate::{
-    Result, judge,
+    Result,
+    judge::{
+        self,
+        payload::{self, DataState},
+    },
     server::{
         self, income,
         outgo::{self, FullVerdict},
@@ -19,39 +32,91 @@ pub struct App<S: outgo::Sender, R: income::Receiver> {
     pub receiver: Arc<R>,
     pub judge_service: Arc<judge::Service>,
     pub cert: Arc<Cert>,
+    pub submissions: Mutex<HashMap<Box<str>, AbortHandle>>,
 }
 
 impl<S: outgo::Sender + Send + Sync + 'static, R: income::Receiver + Send + 'static> App<S, R> {
+    /// `solution` replaces solution from `package`
     pub fn start_judgment(
         self: &Arc<Self>,
-        data: Box<[u8]>,
+        submission: Box<str>,
+        package: Box<dyn AsyncRead + Unpin + Send>,
+        solution: Option<(judge::Lang, Box<[u8]>)>,
     ) -> JoinHandle<crate::Result<judge::api::submission::Result>> {
         let self_clone = Arc::clone(&self);
+        let submission_clone = submission.clone();
         let (sender, mut receiver) = unbounded_channel::<(usize, judge::api::test::Result)>();
+        let data_config = self.judge_service.data_config();
         let handler = tokio::spawn(async move {
+            let mut budget = data_config.submission_limit;
             while let Some((id, test_result)) = receiver.recv().await {
-                let data = archive::pack(&[
-                    ArchiveItem {
-                        path: "output",
-                        data: test_result.output.as_bytes(),
-                    },
-                    ArchiveItem {
-                        path: "message",
-                        data: test_result.message.as_bytes(),
-                    },
-                ])
-                .await
-                .unwrap_or_else(|e| {
-                    log::error!("sending 'TestVerdict': compression error: {e:?}");
-                    vec![].into_boxed_slice()
-                });
+                // output which is already cut while reading is not truncated again
+                let mut texts: Vec<(&str, &str, bool)> = vec![
+                    ("output", &test_result.output, test_result.truncated),
+                    ("message", &test_result.message, false),
+                ];
+                if let Some(comment) = &test_result.comment {
+                    texts.push(("comment", &comment.text, false));
+                }
+                if let Some(transcript) = &test_result.transcript {
+                    texts.push(("transcript", transcript, false));
+                }
+                let mut truncated = test_result.truncated;
+                let texts: Vec<(&str, Cow<str>)> = texts
+                    .into_iter()
+                    .map(|(path, text, already_truncated)| {
+                        match (!already_truncated)
+                            .then(|| payload::truncate(text, data_config.limit))
+                            .flatten()
+                        {
+                            Some(text) => {
+                                truncated = true;
+                                (path, Cow::Owned(text))
+                            }
+                            None => (path, Cow::Borrowed(text)),
+                        }
+                    })
+                    .collect();
+                let items: Vec<ArchiveItem> = texts
+                    .iter()
+                    .map(|(path, text)| ArchiveItem {
+                        path,
+                        data: text.as_bytes(),
+                    })
+                    .collect();
+                // data which failed to be packed or encoded is omitted as exceeding the budget
+                let data = match archive::pack(&items).await {
+                    Ok(data) => data_config.encoding.encode(data).map_err(|e| {
+                        log::error!("sending 'TestVerdict': encoding error: {e:?}");
+                    }),
+                    Err(e) => {
+                        log::error!("sending 'TestVerdict': compression error: {e:?}");
+                        Err(())
+                    }
+                };
+                let (data, data_state) = match data {
+                    Ok(data) if data.len() <= budget => {
+                        budget -= data.len();
+                        let data_state = if truncated {
+                            DataState::Truncated
+                        } else {
+                            DataState::Full
+                        };
+                        (data, data_state)
+                    }
+                    _ => (vec![].into_boxed_slice(), DataState::Omitted),
+                };
                 self_clone
                     .sender
                     .send(server::outgo::Msg::TestVerdict {
+                        submission: submission_clone.clone(),
                         test_id: id,
                         verdict: test_result.verdict,
+                        score: test_result.score,
                         time: test_result.time,
                         memory: test_result.memory,
+                        data_state,
+                        encoding: data_config.encoding,
                         data,
                     })
                     .await
@@ -60,26 +125,41 @@ impl<S: outgo::Sender + Send + Sync + 'static, R: income::Receiver + Send + 'sta
         });
         let self_clone = Arc::clone(&self);
 
-        tokio::spawn(async move {
-            let package = archive::Archive::new(&*data);
+        // the map is locked until the handle is inserted, so the task removes it after
+        let mut submissions = self.submissions.lock().unwrap();
+        let submission_clone = submission.clone();
+        let judgment = tokio::spawn(async move {
+            let package = archive::Archive::new(package);
             let result = Arc::clone(&self_clone.judge_service)
-                .judge(package, sender)
+                .judge(&submission, package, solution, sender)
                 .await;
             _ = handler.await;
+            self_clone.submissions.lock().unwrap().remove(&submission);
             match &result {
                 Ok(full_verdict) => self_clone
                     .sender
-                    .send(server::outgo::Msg::FullVerdict(match full_verdict {
-                        judge::api::submission::Result::Ok {
-                            score,
-                            groups_score,
-                        } => FullVerdict::Ok {
-                            score: *score,
-                            groups_score: groups_score.clone(),
+                    .send(server::outgo::Msg::FullVerdict {
+                        submission: submission.clone(),
+                        verdict: match full_verdict {
+                            judge::api::submission::Result::Ok {
+                                score,
+                                groups_score,
+                                limits,
+                                compilation,
+                                summary,
+                            } => FullVerdict::Ok {
+                                score: *score,
+                                groups_score: groups_score.clone(),
+                                limits: *limits,
+                                compilation: compilation.clone(),
+                                summary: summary.clone(),
+                            },
+                            judge::api::submission::Result::Ce(compilation) => {
+                                FullVerdict::Ce(compilation.clone())
+                            }
+                            judge::api::submission::Result::Te(msg) => FullVerdict::Te(msg.clone()),
                         },
-                        judge::api::submission::Result::Ce(msg) => FullVerdict::Ce(msg.clone()),
-                        judge::api::submission::Result::Te(msg) => FullVerdict::Te(msg.clone()),
-                    }))
+                    })
                     .await
                     .map_err(|e| {
                         log::error!("sending message error: {e:?}");
@@ -89,15 +169,132 @@ impl<S: outgo::Sender + Send + Sync + 'static, R: income::Receiver + Send + 'sta
                     log::error!("judger error: {e:?}");
                     self_clone
                         .sender
-                        .send(server::outgo::Msg::Error {
-                            msg: e.to_string().into_boxed_str(),
-                        })
+                        .send(
+                            if let Some(op_error) = e.downcast_ref::<judge::OperatorError>() {
+                                server::outgo::Msg::OpError {
+                                    submission: submission.clone(),
+                                    msg: op_error.0.clone(),
+                                }
+                            } else {
+                                server::outgo::Msg::Error {
+                                    submission: Some(submission.clone()),
+                                    msg: e.to_string().into_boxed_str(),
+                                }
+                            },
+                        )
                         .await
                         .unwrap();
                 }
             }
             result
-        })
+        });
+        submissions.insert(submission_clone, judgment.abort_handle());
+        judgment
+    }
+
+    /// Custom invocation, cancelled as a submission by `STOP`
+    pub fn start_invocation(
+        self: &Arc<Self>,
+        submission: Box<str>,
+        lang: judge::Lang,
+        limits: judge::api::submission::LimitsOverride,
+        data: Box<[u8]>,
+    ) -> JoinHandle<()> {
+        let self_clone = Arc::clone(&self);
+
+        let mut submissions = self.submissions.lock().unwrap();
+        let submission_clone = submission.clone();
+        let invocation = tokio::spawn(async move {
+            let package = archive::Archive::new(Cursor::new(data));
+            let result = Arc::clone(&self_clone.judge_service)
+                .invoke(&submission, lang, limits, package)
+                .await;
+            self_clone.submissions.lock().unwrap().remove(&submission);
+            let msg = match result {
+                Ok(result) => server::outgo::Msg::RunResult {
+                    submission: submission.clone(),
+                    result,
+                },
+                Err(e) => {
+                    log::error!("invocation error: {e:?}");
+                    server::outgo::Msg::Error {
+                        submission: Some(submission.clone()),
+                        msg: e.to_string().into_boxed_str(),
+                    }
+                }
+            };
+            self_clone
+                .sender
+                .send(msg)
+                .await
+                .expect("websocket closed unexpectedly");
+        });
+        submissions.insert(submission_clone, invocation.abort_handle());
+        invocation
+    }
+
+    /// Hack of target solution, cancelled as a submission by `STOP`
+    pub fn start_hack(
+        self: &Arc<Self>,
+        submission: Box<str>,
+        package: Box<dyn AsyncRead + Unpin + Send>,
+        lang: judge::Lang,
+        group: Option<usize>,
+        data: Box<[u8]>,
+    ) -> JoinHandle<()> {
+        let self_clone = Arc::clone(&self);
+
+        let mut submissions = self.submissions.lock().unwrap();
+        let submission_clone = submission.clone();
+        let hack = tokio::spawn(async move {
+            let result = Arc::clone(&self_clone.judge_service)
+                .hack(
+                    &submission,
+                    archive::Archive::new(package),
+                    lang,
+                    group,
+                    archive::Archive::new(Cursor::new(data)),
+                )
+                .await;
+            self_clone.submissions.lock().unwrap().remove(&submission);
+            let msg = match result {
+                Ok(result) => server::outgo::Msg::HackResult {
+                    submission: submission.clone(),
+                    result,
+                },
+                Err(e) => {
+                    log::error!("hack error: {e:?}");
+                    if let Some(op_error) = e.downcast_ref::<judge::OperatorError>() {
+                        server::outgo::Msg::OpError {
+                            submission: submission.clone(),
+                            msg: op_error.0.clone(),
+                        }
+                    } else {
+                        server::outgo::Msg::Error {
+                            submission: Some(submission.clone()),
+                            msg: e.to_string().into_boxed_str(),
+                        }
+                    }
+                }
+            };
+            self_clone
+                .sender
+                .send(msg)
+                .await
+                .expect("websocket closed unexpectedly");
+        });
+        submissions.insert(submission_clone, hack.abort_handle());
+        hack
+    }
+
+    /// Cancels judging of `submission`, returns `false` if it is not running
+    fn stop_judgment(&self, submission: &str) -> bool {
+        let Some(judgment) = self.submissions.lock().unwrap().remove(submission) else {
+            return false;
+        };
+        judgment.abort();
+        log::info!("submission '{submission}' stopped");
+        true
     }
 
     async fn solve_challenge(&self, challenge: Challenge) -> Result<()> {
@@ -122,12 +319,142 @@ impl<S: outgo::Sender + Send + Sync + 'static, R: income::Receiver + Send + 'sta
                     .solve_challenge(challenge)
                     .await
                     .context("solving auth challenge")?,
-                Msg::Start { data } => _ = self.start_judgment(data),
-                Msg::Stop => self
-                    .judge_service
-                    .cancel_all_tests()
-                    .await
-                    .context("all tests cancelling")?,
+                Msg::Start {
+                    submission,
+                    problem,
+                    data,
+                } => {
+                    if self.submissions.lock().unwrap().contains_key(&submission) {
+                        self.sender
+                            .send(outgo::Msg::Error {
+                                msg: Box::from("submission is already judging"),
+                                submission: Some(submission),
+                            })
+                            .await?;
+                    } else if let Some(problem) = problem {
+                        match self.judge_service.open_problem(&problem.hash).await {
+                            Ok(Some(package)) => {
+                                _ = self.start_judgment(
+                                    submission,
+                                    Box::new(package),
+                                    Some((problem.lang, data)),
+                                )
+                            }
+                            Ok(None) => {
+                                self.sender
+                                    .send(outgo::Msg::NeedProblem {
+                                        submission,
+                                        problem: problem.hash,
+                                    })
+                                    .await?
+                            }
+                            Err(e) => {
+                                log::error!("problem opening error: {e:?}");
+                                self.sender
+                                    .send(outgo::Msg::Error {
+                                        msg: e.to_string().into_boxed_str(),
+                                        submission: Some(submission),
+                                    })
+                                    .await?
+                            }
+                        }
+                    } else {
+                        _ = self.start_judgment(submission, Box::new(Cursor::new(data)), None)
+                    }
+                }
+                Msg::Run {
+                    submission,
+                    lang,
+                    limits,
+                    data,
+                } => {
+                    if self.submissions.lock().unwrap().contains_key(&submission) {
+                        self.sender
+                            .send(outgo::Msg::Error {
+                                msg: Box::from("submission is already judging"),
+                                submission: Some(submission),
+                            })
+                            .await?;
+                    } else {
+                        _ = self.start_invocation(submission, lang, limits, data)
+                    }
+                }
+                Msg::Hack {
+                    submission,
+                    problem,
+                    group,
+                    data,
+                } => {
+                    if self.submissions.lock().unwrap().contains_key(&submission) {
+                        self.sender
+                            .send(outgo::Msg::Error {
+                                msg: Box::from("submission is already judging"),
+                                submission: Some(submission),
+                            })
+                            .await?;
+                        continue;
+                    }
+                    match self.judge_service.open_problem(&problem.hash).await {
+                        Ok(Some(package)) => {
+                            _ = self.start_hack(
+                                submission,
+                                Box::new(package),
+                                problem.lang,
+                                group,
+                                data,
+                            )
+                        }
+                        Ok(None) => {
+                            self.sender
+                                .send(outgo::Msg::NeedProblem {
+                                    submission,
+                                    problem: problem.hash,
+                                })
+                                .await?
+                        }
+                        Err(e) => {
+                            log::error!("problem opening error: {e:?}");
+                            self.sender
+                                .send(outgo::Msg::Error {
+                                    msg: e.to_string().into_boxed_str(),
+                                    submission: Some(submission),
+                                })
+                                .await?
+                        }
+                    }
+                }
+                Msg::Problem { hash, data } => {
+                    if let Err(e) = self.judge_service.add_problem(&hash, &data).await {
+                        log::error!("problem caching error: {e:?}");
+                        self.sender
+                            .send(outgo::Msg::Error {
+                                submission: None,
+                                msg: format!("problem '{hash}': {e}").into_boxed_str(),
+                            })
+                            .await?
+                    }
+                }
+                Msg::Stop {
+                    submission: Some(submission),
+                } => {
+                    if !self.stop_judgment(&submission) {
+                        self.sender
+                            .send(outgo::Msg::Error {
+                                msg: Box::from("submission is not judging"),
+                                submission: Some(submission),
+                            })
+                            .await?;
+                    }
+                }
+                Msg::Stop { submission: None } => {
+                    for (_, judgment) in self.submissions.lock().unwrap().drain() {
+                        judgment.abort();
+                    }
+                    self.judge_service
+                        .cancel_all_tests()
+                        .await
+                        .context("all tests cancelling")?
+                }
                 Msg::Close => break,
             }
         }
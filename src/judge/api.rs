@@ -9,17 +9,30 @@ pub mod test {
     #[derive(Clone)]
     pub struct Result {
         pub verdict: Verdict,
+        pub score: f64, // part of the test score (0 ..= 1)
         pub time: f64,
         pub memory: u64,
 
         pub output: Arc<str>,
+        pub truncated: bool, // output is cut to `data.limit` of judge config
         pub message: Arc<str>,
+        pub comment: Option<Comment>,
+        pub transcript: Option<Arc<str>>, // of interaction with interactor
+    }
+
+    /// Result of __testlib__ checker written into its result file
+    #[derive(Debug, Clone)]
+    pub struct Comment {
+        pub outcome: Box<str>,
+        pub points: Option<f64>, // test score for `points` and `partially-correct` outcomes
+        pub text: Box<str>,
     }
 
     impl Debug for Result {
         fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
             f.debug_struct("Result")
                 .field("verdict", &self.verdict)
+                .field("score", &self.score)
                 .field("time", &self.time)
                 .field("memory", &self.memory)
                 .field(
@@ -30,6 +43,7 @@ pub mod test {
                         .take(VISIBLE_DATA_LEN)
                         .collect::<String>(),
                 )
+                .field("truncated", &self.truncated)
                 .field(
                     "message",
                     &self
@@ -38,14 +52,25 @@ pub mod test {
                         .take(VISIBLE_DATA_LEN)
                         .collect::<String>(),
                 )
+                .field("comment", &self.comment)
+                .field(
+                    "transcript",
+                    &self.transcript.as_ref().map(|transcript| {
+                        transcript
+                            .chars()
+                            .take(VISIBLE_DATA_LEN)
+                            .collect::<String>()
+                    }),
+                )
                 .finish()
         }
     }
 
-    #[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq, Eq)]
+    #[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
     pub enum Verdict {
         Ok, //ok
         Wa, //wrong answer
+        Pc, //partially correct
         Pe, //presentation error
         Ml, //memory limit
         Tl, //time limit
@@ -53,6 +78,8 @@ pub mod test {
         Ce, //compile error
         Te, //testing system error
         Sl, //stack limit
+        Sk, //skipped
+        Fl, //checker failed
     }
 
     impl Verdict {
@@ -79,6 +106,9 @@ pub mod test {
                 match self {
                     Verdict::Ok => "OK",
                     Verdict::Wa => "WA",
+                    Verdict::Pc => "PC",
+                    Verdict::Sk => "SK",
+                    Verdict::Fl => "FL",
                     Verdict::Pe => "PE",
                     Verdict::Ml => "ML",
                     Verdict::Tl => "TL",
@@ -91,19 +121,72 @@ pub mod test {
         }
     }
 }
+pub mod compilation {
+    #[derive(Debug, Clone)]
+    pub struct Report {
+        pub exit_code: Option<u8>, // `None` if compiler was killed
+        pub time: f64,
+        pub memory: u64,
+
+        pub stdout: Box<str>,
+        pub stderr: Box<str>,
+    }
+}
+pub mod invocation {
+    use super::{compilation, test::Verdict};
+
+    /// Single run of solution on custom input
+    #[derive(Debug, Clone)]
+    pub struct Report {
+        pub status: Verdict,       // `OK`, `TL`, `ML` or `RE`
+        pub exit_code: Option<u8>, // `None` if solution was killed
+        pub time: f64,
+        pub memory: u64,
+
+        pub stdout: Box<str>,
+        pub stderr: Box<str>,
+    }
+
+    #[derive(Debug, Clone)]
+    pub enum Result {
+        Ok(Report),
+        Ce(compilation::Report),
+        Te(Box<str>),
+    }
+}
+pub mod hack {
+    use super::{compilation, test};
+
+    #[derive(Debug, Clone)]
+    pub enum Result {
+        Ok {
+            successful: bool, // target solution failed the hack test
+            test: test::Result,
+            answer: Box<str>, // of model solution
+        },
+        Invalid(Box<str>), // validator message
+        Ce(compilation::Report),
+        Te(Box<str>),
+    }
+}
 pub mod submission {
-    use serde::Deserialize;
+    use std::collections::{BTreeMap, HashMap};
 
-    use super::Lang;
+    use serde::{Deserialize, Serialize};
+
+    use super::{Lang, compilation, test};
+    use crate::{judge::builtin::Builtin, prelude::bail};
 
     #[derive(Debug, Deserialize, Clone)]
     #[serde(rename_all = "snake_case")]
     pub enum Type {
         Standard,
         Interactive,
+        DoubleRun,
+        OutputOnly,
     }
 
-    #[derive(Debug, Deserialize, Clone, Copy)]
+    #[derive(Debug, Serialize, Deserialize, Clone, Copy)]
     pub struct Limits {
         pub time: f64,
         pub real_time: f64,
@@ -112,15 +195,195 @@ pub mod submission {
         pub stack: Option<u64>,
     }
 
+    /// `value * multiplier + offset`
+    #[derive(Debug, Serialize, Deserialize, Clone, Copy)]
+    pub struct Scale {
+        #[serde(default = "default_multiplier")]
+        pub multiplier: f64,
+        #[serde(default)]
+        pub offset: f64,
+    }
+
+    fn default_multiplier() -> f64 {
+        1.
+    }
+
+    impl Default for Scale {
+        fn default() -> Self {
+            Self {
+                multiplier: default_multiplier(),
+                offset: 0.,
+            }
+        }
+    }
+
+    impl Scale {
+        pub fn apply(&self, value: f64) -> f64 {
+            value * self.multiplier + self.offset
+        }
+    }
+
+    /// Scales of limits for solutions in some lang
+    #[derive(Debug, Serialize, Deserialize, Clone, Copy, Default)]
+    pub struct LangLimits {
+        #[serde(default)]
+        pub time: Scale,
+        #[serde(default)]
+        pub real_time: Scale,
+        #[serde(default)]
+        pub memory: Scale,
+    }
+
+    impl LangLimits {
+        pub fn apply(&self, limits: Limits) -> Limits {
+            Limits {
+                time: self.time.apply(limits.time),
+                real_time: self.real_time.apply(limits.real_time),
+                memory: self.memory.apply(limits.memory as f64).round() as u64,
+                stack: limits.stack,
+            }
+        }
+    }
+
+    /// Replaces set limits of group or test
+    #[derive(Debug, Deserialize, Clone, Copy, Default)]
+    pub struct LimitsOverride {
+        pub time: Option<f64>,
+        pub real_time: Option<f64>,
+        pub memory: Option<u64>,
+        pub stack: Option<u64>,
+    }
+
+    impl LimitsOverride {
+        pub fn apply(&self, limits: Limits) -> Limits {
+            Limits {
+                time: self.time.unwrap_or(limits.time),
+                real_time: self.real_time.unwrap_or(limits.real_time),
+                memory: self.memory.unwrap_or(limits.memory),
+                stack: self.stack.or(limits.stack),
+            }
+        }
+    }
+
     #[derive(Debug, Deserialize, Clone)]
     pub struct TestsRange(pub usize, pub usize);
 
+    #[derive(Debug, Deserialize, Clone, Copy, Default, PartialEq, Eq)]
+    #[serde(rename_all = "snake_case")]
+    pub enum Scoring {
+        #[default]
+        AllOrNothing,
+        Sum,
+        Min,
+        Product,
+        MaxOfTests,
+    }
+
+    impl Scoring {
+        /// Score of group by scores of its tests (0 ..= 1), skipped tests are zeros.
+        /// Group without tests gets full `cost` by every policy
+        pub fn score(&self, cost: f64, tests: &[f64]) -> f64 {
+            if tests.is_empty() {
+                return cost;
+            }
+            match self {
+                Self::AllOrNothing => {
+                    if tests.iter().all(|score| *score >= 1.) {
+                        cost
+                    } else {
+                        0.
+                    }
+                }
+                Self::Sum => cost * tests.iter().sum::<f64>() / tests.len() as f64,
+                Self::Min => cost * tests.iter().copied().fold(1., f64::min),
+                Self::Product => cost * tests.iter().product::<f64>(),
+                Self::MaxOfTests => cost * tests.iter().copied().fold(0., f64::max),
+            }
+        }
+
+        /// Whether group score is zero regardless of other tests after test with `score`
+        pub fn is_lost(&self, score: f64) -> bool {
+            match self {
+                Self::AllOrNothing => score < 1.,
+                Self::Min | Self::Product => score <= 0.,
+                Self::Sum | Self::MaxOfTests => false,
+            }
+        }
+    }
+
     #[derive(Debug, Deserialize, Clone)]
     pub struct Group {
         pub id: usize,
         pub range: TestsRange,
-        pub cost: usize,
+        pub cost: f64,
         pub depends: Box<[usize]>,
+        #[serde(default)]
+        pub scoring: Scoring,
+        #[serde(default)]
+        pub limits: LimitsOverride,
+    }
+
+    /// Checker of test output: `checker.out` from package or `builtin:<name>`
+    #[derive(Debug, Deserialize, Clone, Copy, Default, PartialEq)]
+    #[serde(try_from = "String")]
+    pub enum Checker {
+        #[default]
+        Package,
+        Builtin(Builtin),
+    }
+
+    impl TryFrom<String> for Checker {
+        type Error = crate::Error;
+
+        fn try_from(value: String) -> crate::Result<Self> {
+            match value.strip_prefix("builtin:") {
+                Some(name) => Ok(Self::Builtin(name.parse()?)),
+                None => bail!("checker must be 'builtin:<name>', got '{value}'"),
+            }
+        }
+    }
+
+    /// Recording of interaction between solution and interactor
+    #[derive(Debug, Deserialize, Clone, Copy)]
+    pub struct TranscriptConfig {
+        #[serde(default = "default_transcript_limit")]
+        pub limit: usize, // bytes
+    }
+
+    fn default_transcript_limit() -> usize {
+        1 << 16
+    }
+
+    #[derive(Debug, Deserialize, Clone)]
+    pub struct Grader {
+        pub sources: Box<[Box<str>]>,
+        #[serde(default)]
+        pub headers: Box<[Box<str>]>,
+    }
+
+    #[derive(Debug, Deserialize, Clone, Default)]
+    pub struct Instance {
+        #[serde(default)]
+        pub args: Box<[Box<str>]>,
+        pub program: Option<Box<str>>, // source in package, run instead of the solution
+        pub lang: Option<Lang>,        // of `program`, solution lang by default
+    }
+
+    fn default_instances() -> Box<[Instance]> {
+        Box::from([Instance::default()])
+    }
+
+    #[derive(Debug, Deserialize, Clone)]
+    pub struct Model {
+        pub lang: Lang,
+        pub source: Box<str>,
+    }
+
+    #[derive(Debug, Deserialize, Clone)]
+    pub struct Generation {
+        #[serde(default)]
+        pub tests: HashMap<usize, Box<str>>,
+        pub model: Option<Model>,
     }
 
     #[derive(Debug, Deserialize)]
@@ -129,15 +392,112 @@ pub mod submission {
         pub lang: Lang,
         pub limits: Limits,
         pub groups: Box<[Group]>,
+        pub grader: Option<Grader>,
+        #[serde(default = "default_instances")]
+        pub instances: Box<[Instance]>,
+        pub generation: Option<Generation>,
+        #[serde(default)]
+        pub checker: Checker,
+        pub transcript: Option<TranscriptConfig>,
+        #[serde(default)]
+        pub checker_limits: LimitsOverride,
+        #[serde(default)]
+        pub interactor_limits: LimitsOverride,
+        #[serde(default)]
+        pub test_limits: HashMap<usize, LimitsOverride>,
+        #[serde(default)]
+        pub lang_limits: HashMap<Lang, LangLimits>,
+    }
+
+    impl Task {
+        /// Limits of test `test_id` (numbered from one) in group `group_id`
+        pub fn test_limits(&self, group_id: usize, test_id: usize) -> Limits {
+            let limits = self.groups[group_id].limits.apply(self.limits);
+            match self.test_limits.get(&test_id) {
+                Some(limits_override) => limits_override.apply(limits),
+                None => limits,
+            }
+        }
+    }
+
+    /// Statistics of all tests of submission
+    #[derive(Debug, Clone, Default)]
+    pub struct Summary {
+        pub max_time: f64,
+        pub max_memory: u64,
+        pub groups_failure: Box<[Option<(usize, test::Verdict)>]>, // first failed test (numbered from one)
+        pub verdicts: BTreeMap<test::Verdict, usize>,              // count of tests with verdict
     }
 
     #[derive(Debug, Clone)]
     pub enum Result {
         Ok {
-            score: usize,
-            groups_score: Box<[usize]>,
+            score: f64,
+            groups_score: Box<[f64]>,
+            limits: Limits, // of the task for solution lang
+            compilation: Option<compilation::Report>,
+            summary: Summary,
         },
-        Ce(Box<str>),
+        Ce(compilation::Report),
         Te(Box<str>),
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::submission::Scoring;
+
+    const POLICIES: [Scoring; 5] = [
+        Scoring::AllOrNothing,
+        Scoring::Sum,
+        Scoring::Min,
+        Scoring::Product,
+        Scoring::MaxOfTests,
+    ];
+
+    #[test]
+    fn empty_group_gets_full_cost() {
+        for scoring in POLICIES {
+            assert_eq!(scoring.score(10., &[]), 10., "{scoring:?}");
+        }
+    }
+
+    #[test]
+    fn passed_group_gets_full_cost() {
+        for scoring in POLICIES {
+            assert_eq!(scoring.score(10., &[1., 1.]), 10., "{scoring:?}");
+        }
+    }
+
+    #[test]
+    fn partial_group_score() {
+        let tests = [1., 0.5, 0.5, 0.];
+        assert_eq!(Scoring::AllOrNothing.score(10., &tests), 0.);
+        assert_eq!(Scoring::Sum.score(10., &tests), 5.);
+        assert_eq!(Scoring::Min.score(10., &tests), 0.);
+        assert_eq!(Scoring::Product.score(10., &tests), 0.);
+        assert_eq!(Scoring::MaxOfTests.score(10., &tests), 10.);
+
+        let tests = [1., 0.5, 0.5];
+        assert_eq!(Scoring::AllOrNothing.score(10., &tests), 0.);
+        assert_eq!(Scoring::Sum.score(12., &tests), 8.);
+        assert_eq!(Scoring::Min.score(10., &tests), 5.);
+        assert_eq!(Scoring::Product.score(10., &tests), 2.5);
+        assert_eq!(Scoring::MaxOfTests.score(10., &tests), 10.);
+    }
+
+    #[test]
+    fn group_is_lost_only_when_score_is_zero_for_sure() {
+        for (scoring, failed, partial) in [
+            (Scoring::AllOrNothing, true, true),
+            (Scoring::Sum, false, false),
+            (Scoring::Min, true, false),
+            (Scoring::Product, true, false),
+            (Scoring::MaxOfTests, false, false),
+        ] {
+            assert!(!scoring.is_lost(1.), "{scoring:?}");
+            assert_eq!(scoring.is_lost(0.), failed, "{scoring:?}");
+            assert_eq!(scoring.is_lost(0.5), partial, "{scoring:?}");
+        }
+    }
+}
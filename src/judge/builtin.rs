@@ -0,0 +1,310 @@
+use std::str::FromStr;
+
+use super::api::test;
+use crate::{Error, Result, prelude::*};
+
+const DEFAULT_EPS: f64 = 1e-6;
+const VISIBLE_LEN: usize = 32;
+
+/// Checker implemented by judge, compares output with correct answer without sandbox
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Builtin {
+    Exact,
+    Lines,
+    Tokens,
+    TokensCi,
+    Floats(f64), // absolute or relative epsilon
+    YesNo,
+}
+
+impl FromStr for Builtin {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let (name, arg) = s.split_once(':').unwrap_or((s, ""));
+        Ok(match (name, arg) {
+            ("exact", "") => Self::Exact,
+            ("lines", "") => Self::Lines,
+            ("tokens", "") => Self::Tokens,
+            ("tokens_ci", "") => Self::TokensCi,
+            ("floats", "") => Self::Floats(DEFAULT_EPS),
+            ("floats", eps) => Self::Floats(
+                eps.parse()
+                    .with_context(|| format!("incorrect epsilon '{eps}'"))?,
+            ),
+            ("yesno", "") => Self::YesNo,
+            _ => bail!("unknown builtin checker '{s}'"),
+        })
+    }
+}
+
+fn visible(s: &str) -> String {
+    if s.chars().count() > VISIBLE_LEN {
+        format!("{}...", s.chars().take(VISIBLE_LEN).collect::<String>())
+    } else {
+        s.to_string()
+    }
+}
+
+fn count(n: usize) -> String {
+    match n % 10 {
+        1 if n % 100 != 11 => format!("{n}st"),
+        2 if n % 100 != 12 => format!("{n}nd"),
+        3 if n % 100 != 13 => format!("{n}rd"),
+        _ => format!("{n}th"),
+    }
+}
+
+/// Compares sequences item by item, returns message about the first difference
+fn first_difference<'a>(
+    what: &str,
+    output: impl Iterator<Item = &'a str>,
+    correct: impl Iterator<Item = &'a str>,
+    eq: impl Fn(&str, &str) -> bool,
+) -> Option<String> {
+    let mut output = output.fuse();
+    let mut correct = correct.fuse();
+    for i in 1.. {
+        match (output.next(), correct.next()) {
+            (None, None) => return None,
+            (Some(found), Some(expected)) if eq(found, expected) => (),
+            (Some(found), Some(expected)) => {
+                return Some(format!(
+                    "{} {what} differ - expected: '{}', found: '{}'",
+                    count(i),
+                    visible(expected),
+                    visible(found)
+                ));
+            }
+            (Some(found), None) => {
+                return Some(format!(
+                    "extra {what} in output, {}: '{}'",
+                    count(i),
+                    visible(found)
+                ));
+            }
+            (None, Some(expected)) => {
+                return Some(format!(
+                    "unexpected end of output, {} {what} expected: '{}'",
+                    count(i),
+                    visible(expected)
+                ));
+            }
+        }
+    }
+    None
+}
+
+fn floats_eq(eps: f64, found: &str, expected: &str) -> bool {
+    let (Ok(found), Ok(expected)) = (found.parse::<f64>(), expected.parse::<f64>()) else {
+        return false;
+    };
+    let diff = (found - expected).abs();
+    diff <= eps || diff <= eps * expected.abs()
+}
+
+impl Builtin {
+    /// Returns verdict, test score and first difference comment,
+    /// verdict is `FL` if correct answer is invalid
+    pub fn check(&self, output: &[u8], correct: &[u8]) -> (test::Verdict, f64, String) {
+        // only text checkers decode outputs, `exact` compares raw bytes
+        let text = || {
+            (
+                String::from_utf8_lossy(output),
+                String::from_utf8_lossy(correct),
+            )
+        };
+        let difference = match self {
+            Self::Exact => match output.iter().zip(correct).position(|(a, b)| a != b) {
+                None if output.len() == correct.len() => None,
+                position => Some(format!(
+                    "outputs differ at byte {}",
+                    position.unwrap_or(output.len().min(correct.len()))
+                )),
+            },
+            Self::Lines => {
+                let (output, correct) = text();
+                let lines = |text: &str| {
+                    let mut lines: Vec<String> = text
+                        .lines()
+                        .map(|line| line.split_whitespace().collect::<Vec<_>>().join(" "))
+                        .collect();
+                    while lines.last().is_some_and(|line| line.is_empty()) {
+                        lines.pop();
+                    }
+                    lines
+                };
+                let (output, correct) = (lines(&output), lines(&correct));
+                first_difference(
+                    "lines",
+                    output.iter().map(|s| s.as_str()),
+                    correct.iter().map(|s| s.as_str()),
+                    |a, b| a == b,
+                )
+            }
+            Self::Tokens => {
+                let (output, correct) = text();
+                first_difference(
+                    "words",
+                    output.split_whitespace(),
+                    correct.split_whitespace(),
+                    |a, b| a == b,
+                )
+            }
+            Self::TokensCi => {
+                let (output, correct) = text();
+                first_difference(
+                    "words",
+                    output.split_whitespace(),
+                    correct.split_whitespace(),
+                    |a, b| a.to_lowercase() == b.to_lowercase(),
+                )
+            }
+            Self::Floats(eps) => {
+                let (output, correct) = text();
+                first_difference(
+                    "numbers",
+                    output.split_whitespace(),
+                    correct.split_whitespace(),
+                    |a, b| floats_eq(*eps, a, b),
+                )
+            }
+            Self::YesNo => {
+                let (output, correct) = text();
+                let answer = |text: &str| text.split_whitespace().next().map(str::to_lowercase);
+                // bad correct answer is a jury error, not a wrong answer of the solution
+                match (answer(&output), answer(&correct)) {
+                    (_, None) => {
+                        return (test::Verdict::Fl, 0., "correct answer is empty".to_string());
+                    }
+                    (_, Some(expected)) if expected != "yes" && expected != "no" => {
+                        return (
+                            test::Verdict::Fl,
+                            0.,
+                            format!("correct answer is not yes/no: '{}'", visible(&expected)),
+                        );
+                    }
+                    (Some(found), Some(expected)) if found == expected => None,
+                    (found, Some(expected)) => Some(format!(
+                        "expected: '{expected}', found: '{}'",
+                        visible(found.as_deref().unwrap_or(""))
+                    )),
+                }
+            }
+        };
+        match difference {
+            None => (test::Verdict::Ok, 1., "ok".into()),
+            Some(message) => (test::Verdict::Wa, 0., message),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn verdict(checker: &str, output: &str, correct: &str) -> test::Verdict {
+        checker
+            .parse::<Builtin>()
+            .unwrap()
+            .check(output.as_bytes(), correct.as_bytes())
+            .0
+    }
+
+    #[test]
+    fn names() {
+        assert_eq!("exact".parse::<Builtin>().unwrap(), Builtin::Exact);
+        assert_eq!("tokens_ci".parse::<Builtin>().unwrap(), Builtin::TokensCi);
+        assert_eq!(
+            "floats".parse::<Builtin>().unwrap(),
+            Builtin::Floats(DEFAULT_EPS)
+        );
+        assert_eq!(
+            "floats:0.01".parse::<Builtin>().unwrap(),
+            Builtin::Floats(0.01)
+        );
+        assert!("floats:eps".parse::<Builtin>().is_err());
+        assert!("lines:1".parse::<Builtin>().is_err());
+        assert!("unknown".parse::<Builtin>().is_err());
+    }
+
+    #[test]
+    fn exact() {
+        assert_eq!(verdict("exact", "1 2\n", "1 2\n"), test::Verdict::Ok);
+        assert_eq!(verdict("exact", "1 2", "1 2\n"), test::Verdict::Wa);
+        assert_eq!(verdict("exact", "1  2\n", "1 2\n"), test::Verdict::Wa);
+        let (_, _, message) = Builtin::Exact.check(b"abd", b"abc");
+        assert_eq!(message, "outputs differ at byte 2");
+    }
+
+    #[test]
+    fn lines() {
+        assert_eq!(verdict("lines", "1  2 \n3\n", "1 2\n3"), test::Verdict::Ok);
+        assert_eq!(
+            verdict("lines", "\t1 2\r\n3\n\n\n", "1 2\n3\n"),
+            test::Verdict::Ok
+        );
+        assert_eq!(verdict("lines", "1 2 3\n", "1 2\n3\n"), test::Verdict::Wa);
+        assert_eq!(
+            verdict("lines", "1 2\n\n3\n", "1 2\n3\n"),
+            test::Verdict::Wa
+        );
+        let (_, _, message) = Builtin::Lines.check(b"a\nb\n", b"a\nc\n");
+        assert_eq!(message, "2nd lines differ - expected: 'c', found: 'b'");
+    }
+
+    #[test]
+    fn tokens() {
+        assert_eq!(verdict("tokens", "1\n2  3", "1 2 3\n"), test::Verdict::Ok);
+        assert_eq!(verdict("tokens", "1 2", "1 2 3"), test::Verdict::Wa);
+        assert_eq!(verdict("tokens", "1 2 3 4", "1 2 3"), test::Verdict::Wa);
+        assert_eq!(verdict("tokens", "Yes", "yes"), test::Verdict::Wa);
+    }
+
+    #[test]
+    fn tokens_ci() {
+        assert_eq!(
+            verdict("tokens_ci", "Hello WORLD", "hello world"),
+            test::Verdict::Ok
+        );
+        assert_eq!(verdict("tokens_ci", "Привет", "привет"), test::Verdict::Ok);
+        assert_eq!(
+            verdict("tokens_ci", "hello", "hello world"),
+            test::Verdict::Wa
+        );
+    }
+
+    #[test]
+    fn floats() {
+        assert_eq!(verdict("floats", "1.0000001 2", "1 2.0"), test::Verdict::Ok);
+        assert_eq!(verdict("floats", "1.00001", "1"), test::Verdict::Wa);
+        assert_eq!(verdict("floats:0.01", "1.005", "1"), test::Verdict::Ok);
+        assert_eq!(verdict("floats:0.01", "1.02", "1"), test::Verdict::Wa);
+        // relative error for large numbers
+        assert_eq!(
+            verdict("floats", "1000000001", "1000000000"),
+            test::Verdict::Ok
+        );
+        assert_eq!(
+            verdict("floats", "1000010000", "1000000000"),
+            test::Verdict::Wa
+        );
+        assert_eq!(verdict("floats", "nan", "1"), test::Verdict::Wa);
+        assert_eq!(verdict("floats", "1", "1 2"), test::Verdict::Wa);
+    }
+
+    #[test]
+    fn yesno() {
+        assert_eq!(verdict("yesno", "YES\n", "yes"), test::Verdict::Ok);
+        assert_eq!(verdict("yesno", "No extra", "NO"), test::Verdict::Ok);
+        assert_eq!(verdict("yesno", "no", "yes"), test::Verdict::Wa);
+        assert_eq!(verdict("yesno", "", "yes"), test::Verdict::Wa);
+        assert_eq!(verdict("yesno", "maybe", "no"), test::Verdict::Wa);
+    }
+
+    #[test]
+    fn yesno_bad_correct_answer() {
+        assert_eq!(verdict("yesno", "yes", ""), test::Verdict::Fl);
+        assert_eq!(verdict("yesno", "yes", "maybe"), test::Verdict::Fl);
+    }
+}
@@ -0,0 +1,74 @@
+use std::{io::ErrorKind, time::SystemTime};
+
+use tokio::fs::{File, copy, read_dir, remove_file, rename};
+
+use crate::{Result, prelude::*};
+
+const TMP_EXT: &str = "tmp";
+
+/// Path for writing a cache entry, which appears at `path` only after `rename`
+pub fn tmp_path(path: &str) -> String {
+    format!("{path}.{}.{TMP_EXT}", rand::random::<u64>())
+}
+
+/// Marks cached file as recently used
+pub async fn touch(file: File) -> Result<File> {
+    let file = file.into_std().await;
+    file.set_modified(SystemTime::now())
+        .context("cache entry touching")?;
+    Ok(File::from_std(file))
+}
+
+/// Copies cached `from` to `to` and marks it as recently used,
+/// returns `false` if it is not cached (or was just evicted)
+pub async fn take(from: &str, to: &str) -> Result<bool> {
+    match copy(from, to).await {
+        Ok(_) => (),
+        Err(e) if e.kind() == ErrorKind::NotFound => return Ok(false),
+        Err(e) => return Err(e.into()),
+    }
+    if let Ok(file) = File::open(from).await {
+        touch(file).await?;
+    }
+    Ok(true)
+}
+
+/// Copies `from` into cache as `to`
+pub async fn put(from: &str, to: &str) -> Result<()> {
+    let tmp_path = tmp_path(to);
+    copy(from, &tmp_path).await?;
+    rename(&tmp_path, to).await?;
+    Ok(())
+}
+
+/// Removes least recently used files of `dir` while their total size is larger than `size_limit`,
+/// entries which are being written are kept
+pub async fn evict(dir: &str, size_limit: u64) -> Result<()> {
+    let mut entries = vec![];
+    let mut dir_entries = read_dir(dir).await?;
+    while let Some(entry) = dir_entries.next_entry().await? {
+        if entry.path().extension().is_some_and(|ext| ext == TMP_EXT) {
+            continue;
+        }
+        // entry may be evicted by another job at the same time
+        let Ok(metadata) = entry.metadata().await else {
+            continue;
+        };
+        entries.push((metadata.modified()?, metadata.len(), entry.path()));
+    }
+    entries.sort();
+
+    let mut size: u64 = entries.iter().map(|(_, len, _)| len).sum();
+    for (_, len, path) in entries {
+        if size <= size_limit {
+            break;
+        }
+        match remove_file(&path).await {
+            Ok(()) => log::info!("cache entry '{}' evicted", path.display()),
+            Err(e) if e.kind() == ErrorKind::NotFound => (),
+            Err(e) => return Err(e.into()),
+        }
+        size -= len;
+    }
+    Ok(())
+}
@@ -1,33 +1,41 @@
-// IN PROGRESS
-
 use std::sync::Arc;
 
-use tokio::{fs::File, io::AsyncReadExt as _, task::JoinHandle};
+use async_trait::async_trait;
+use tokio::fs::File;
 
 use super::{
     Lang, SOLUTION_EXT, SOLUTION_NAME,
     api::{submission, test},
-    path_from,
+    path_from, payload,
+    standard::{
+        CHECKER_EXT, CHECKER_NAME, CORRECT_DIR, CORRECT_EXT, INPUT_DIR, INPUT_EXT,
+        TARGET_CHECKER_PATH, TARGET_CORRECT_PATH, TARGET_INPUT_PATH, TARGET_OUTPUT_PATH,
+        TARGET_SOLUTION_PATH, check,
+    },
 };
 use crate::{
     LogState, Result,
-    sandbox::{self, MaybeLimited, RunConfig, RunStatus},
+    sandbox::{self, Command, MaybeLimited::*, RunResult, RunStatus},
 };
 
-const CHECKER_NAME: &str = "checker";
-const CHECKER_EXT: Option<&str> = Some("out");
+const TRANSFORMER_NAME: &str = "transformer";
+const TRANSFORMER_EXT: Option<&str> = Some("out");
 
-const INPUT_DIR: &str = "input";
-const INPUT_EXT: Option<&str> = Some("txt");
+const TARGET_FIRST_OUTPUT_PATH: &str = "first_out.txt";
+const TARGET_SECOND_INPUT_PATH: &str = "second_in.txt";
+const TARGET_TRANSFORMER_ERROR_PATH: &str = "transformer_err.txt";
 
-const CORRECT_DIR: &str = "correct";
-const CORRECT_EXT: Option<&str> = Some("txt");
+const TARGET_TRANSFORMER_PATH: &str = "transformer.out";
 
 pub struct Enviroment {
-    sandbox: Arc<sandbox::Sandbox>,
+    first_sandbox: Arc<sandbox::Sandbox>,
+    second_sandbox: Arc<sandbox::Sandbox>,
     limits: submission::Limits,
     lang: Lang,
+    checker: submission::Checker,
+    checker_limits: submission::LimitsOverride,
 
+    data_limit: usize,
     work_dir: Box<str>,
     test_id: usize,
     log_state: Arc<LogState>,
@@ -37,30 +45,69 @@ pub async fn prepare(
     sandboxes: Arc<sandbox::Service>,
     lang: Lang,
     limits: submission::Limits,
+    checker: submission::Checker,
+    checker_limits: submission::LimitsOverride,
+    data_limit: usize,
     work_dir: Box<str>,
 
     test_id: usize,
     log_state: Arc<LogState>,
 ) -> Result<Enviroment> {
-    let sandbox = Arc::new(sandboxes.initialize_sandbox().await?);
+    let mut boxes = sandboxes
+        .initialize_sandboxes(2)
+        .await?
+        .into_iter()
+        .map(Arc::new);
+    let (first_sandbox, second_sandbox) = (boxes.next().unwrap(), boxes.next().unwrap());
 
-    let log_state = log_state.push("box_id", &*format!("{}", sandbox.id()));
+    let log_state = log_state.push("first_box_id", &*format!("{}", first_sandbox.id()));
+    let log_state = log_state.push("second_box_id", &*format!("{}", second_sandbox.id()));
 
     Ok(Enviroment {
-        sandbox,
+        first_sandbox,
+        second_sandbox,
         lang,
         limits,
+        checker,
+        checker_limits,
+        data_limit,
         work_dir,
         test_id,
         log_state,
     })
 }
 
+impl Enviroment {
+    async fn run_solution(
+        &self,
+        sandbox: &sandbox::Sandbox,
+        input: &str,
+        output: &str,
+    ) -> Result<RunResult> {
+        let mut solution_cmd = self.lang.command_to_run(TARGET_SOLUTION_PATH);
+        solution_cmd
+            .time(Limited(self.limits.time))
+            .memory(Limited(self.limits.memory))
+            .real_time(Limited(self.limits.real_time));
+        if let Some(stack) = self.limits.stack {
+            solution_cmd.stack(Limited(stack));
+        }
+        solution_cmd
+            .count_files(Limited(4))
+            .count_process(Limited(1))
+            .stdin(input)
+            .stdout(output);
+
+        sandbox.run(&solution_cmd).await
+    }
+}
+
+#[async_trait]
 impl super::Enviroment for Enviroment {
     async fn run(self: Box<Self>) -> Result<test::Result> {
-        let log_state = self.log_state.push("task type", "STANDARD");
+        let log_state = self.log_state.push("task type", "DOUBLE_RUN");
         log::trace!("({log_state}) testing STARTED");
-        let src_input_path = super::path_from(
+        let src_input_path = path_from(
             &format!("{}/{INPUT_DIR}", self.work_dir),
             &format!("{}", self.test_id + 1),
             INPUT_EXT,
@@ -71,23 +118,13 @@ impl super::Enviroment for Enviroment {
             CORRECT_EXT,
         );
         let src_checker_path = path_from(&self.work_dir, CHECKER_NAME, CHECKER_EXT);
-
+        let src_transformer_path = path_from(&self.work_dir, TRANSFORMER_NAME, TRANSFORMER_EXT);
         let src_solution_path = path_from(&self.work_dir, SOLUTION_NAME, SOLUTION_EXT);
 
-        const TARGET_INPUT_PATH: &str = "in.txt";
-        const TARGET_CORRECT_PATH: &str = "correct.txt";
-        const TARGET_OUTPUT_PATH: &str = "out.txt";
-        const TARGET_CHECKER_OUTPUT_PATH: &str = "checker_out.txt";
-        const TARGET_CHECKER_ERROR_PATH: &str = "checker_err.txt";
-
-        const TARGET_CHECKER_PATH: &str = "checker.out";
-        const TARGET_SOLUTION_PATH: &str = "solution.out";
-
-        Arc::clone(&self.sandbox)
+        Arc::clone(&self.first_sandbox)
             .write_group_into_box(
                 vec![
                     (File::open(&*src_input_path).await?, TARGET_INPUT_PATH),
-                    (File::open(&*src_checker_path).await?, TARGET_CHECKER_PATH),
                     (File::open(&*src_solution_path).await?, TARGET_SOLUTION_PATH),
                 ]
                 .into_iter()
@@ -96,152 +133,205 @@ impl super::Enviroment for Enviroment {
             )
             .await?;
 
-        let solution_result = match self
-            .sandbox
-            .run(
-                self.lang.run_command(TARGET_SOLUTION_PATH),
-                RunConfig {
-                    time_limit: MaybeLimited::Limited(self.limits.time),
-                    memory_limit: MaybeLimited::Limited(self.limits.memory),
-                    real_time_limit: self.limits.real_time,
-                    extra_time_limit: None,
-                    stack_limit: self.limits.stack.map(|s| MaybeLimited::Limited(s)),
-                    open_files_limit: None,
-                    process_limit: Some(MaybeLimited::Limited(1)),
-                    env: false,
-                    open_dirs: Box::from(vec![]),
-
-                    stdin: Some(TARGET_INPUT_PATH.to_string().into_boxed_str()),
-                    stdout: Some(TARGET_OUTPUT_PATH.to_string().into_boxed_str()),
-                    stderr: None,
-                },
+        let first_result = match self
+            .run_solution(
+                &self.first_sandbox,
+                TARGET_INPUT_PATH,
+                TARGET_FIRST_OUTPUT_PATH,
             )
             .await
         {
             Ok(res) => res,
             Err(e) => {
-                log::error!("({log_state}) solution run error: {e:?}");
+                log::error!("({log_state}) first solution run error: {e:?}");
                 return Err(e);
             }
         };
 
-        let mut output_file = self.sandbox.read_from_box(TARGET_OUTPUT_PATH).await?;
-        let mut output = String::new();
-        output_file.read_to_string(&mut output).await?;
-        let output = Arc::from(output.as_str());
+        let (first_output, first_truncated) = payload::read_truncated(
+            self.first_sandbox
+                .read_from_box(TARGET_FIRST_OUTPUT_PATH)
+                .await?,
+            self.data_limit,
+        )
+        .await?;
+        let first_output: Arc<str> = Arc::from(first_output);
 
-        if let Some(verdict) = test::Verdict::from_run_status(solution_result.status) {
+        if let Some(verdict) = test::Verdict::from_run_status(first_result.status) {
             return Ok(test::Result {
                 verdict,
-                time: solution_result.time,
-                memory: solution_result.memory,
-                output,
+                score: 0.,
+                time: first_result.time,
+                memory: first_result.memory,
+                output: first_output,
+                truncated: first_truncated,
                 message: Arc::from(
                     format!(
-                        "ISOLATE: {}",
-                        solution_result.status_message.unwrap_or(Box::from("-"))
+                        "first run: ISOLATE: {}",
+                        first_result.status_message.unwrap_or(Box::from("-"))
                     )
                     .as_str(),
                 ),
+                comment: None,
+                transcript: None,
             });
         }
 
-        if let Ok(mut correct) = File::open(&*src_correct_path).await {
-            self.sandbox
-                .write_into_box(&mut correct, TARGET_CORRECT_PATH)
+        let second_input_src = if let Ok(mut transformer) = File::open(&*src_transformer_path).await
+        {
+            self.first_sandbox
+                .write_into_box(&mut transformer, TARGET_TRANSFORMER_PATH)
                 .await?;
-        } else {
-            log::debug!("({log_state}) correct file not founded");
-        }
 
-        let checker_result = match self.sandbox
-                .run(
-                    format!("./{TARGET_CHECKER_PATH} {TARGET_INPUT_PATH} {TARGET_OUTPUT_PATH} {TARGET_CORRECT_PATH}")
-                        .into_boxed_str(),
-                    RunConfig {
-                        time_limit: MaybeLimited::Limited(self.limits.time),
-                        memory_limit: MaybeLimited::Unlimited,
-                        real_time_limit: self.limits.real_time,
-                        extra_time_limit: None,
-                        stack_limit: Some(MaybeLimited::Unlimited),
-                        open_files_limit: Some(MaybeLimited::Unlimited),
-                        process_limit: None,
-
-                        env: false,
-                        open_dirs: Box::from(vec![]),
-
-
-                        stdout: Some(TARGET_CHECKER_OUTPUT_PATH.to_string().into_boxed_str()),
-                        stdin: None,
-                        stderr: Some(TARGET_CHECKER_ERROR_PATH.to_string().into_boxed_str()),
-                    },
-                )
-                .await
-            {
+            let mut transformer_cmd = Command::new(format!("./{TARGET_TRANSFORMER_PATH}"));
+            transformer_cmd
+                .args([TARGET_INPUT_PATH, TARGET_FIRST_OUTPUT_PATH])
+                .count_files(Unlimited)
+                .count_process(Unlimited)
+                .stdout(TARGET_SECOND_INPUT_PATH)
+                .stderr(TARGET_TRANSFORMER_ERROR_PATH);
+
+            let transformer_result = match self.first_sandbox.run(&transformer_cmd).await {
                 Ok(res) => res,
                 Err(e) => {
-                    log::error!("({log_state}) checker error: {e:?}");
+                    log::error!("({log_state}) transformer error: {e:?}");
                     return Err(e);
                 }
             };
 
-        let sandbox_clone = Arc::clone(&self.sandbox);
-        let checker_output_handler: JoinHandle<Result<String>> = tokio::spawn(async move {
-            let mut output = String::new();
-            sandbox_clone
-                .read_from_box(TARGET_CHECKER_OUTPUT_PATH)
-                .await?
-                .read_to_string(&mut output)
-                .await?;
-            Ok(output)
-        });
-
-        let sandbox_clone = Arc::clone(&self.sandbox);
-        let checker_error_handler: JoinHandle<Result<String>> = tokio::spawn(async move {
-            let mut output = String::new();
-            sandbox_clone
-                .read_from_box(TARGET_CHECKER_ERROR_PATH)
-                .await?
-                .read_to_string(&mut output)
-                .await?;
-            Ok(output)
-        });
-
-        let checker_output = checker_output_handler.await?.unwrap_or("-".to_string());
-        let checker_error = checker_error_handler.await?.unwrap_or("-".to_string());
-
-        let (verdict, message) = match checker_result.status {
-            RunStatus::Ml | RunStatus::Tl | RunStatus::Sg(_) => (
-                test::Verdict::Te,
-                format!(
-                    "checker_output: {checker_output}\n, checker_error: {checker_error}\n 'isolate': {}",
-                    checker_result.status_message.as_deref().unwrap_or("")
-                ),
-            ),
-            RunStatus::Ok => (
-                test::Verdict::Ok,
-                format!(
-                    "checker_output: {checker_output}\n, checker_error: {checker_error}\n 'isolate': {}",
-                    checker_result.status_message.as_deref().unwrap_or("")
-                ),
-            ),
-            RunStatus::Re(code) => (
-                match code {
-                    1 => test::Verdict::Wa,
-                    2 => test::Verdict::Pe,
-                    _ => test::Verdict::Te,
-                },
-                format!("checker_output: {checker_output}\n, checker_error: {checker_error}"),
-            ),
+            if transformer_result.status != RunStatus::Ok {
+                let transformer_error = self
+                    .first_sandbox
+                    .read_string_from_box(TARGET_TRANSFORMER_ERROR_PATH)
+                    .await
+                    .unwrap_or("-".to_string());
+                return Ok(test::Result {
+                    verdict: test::Verdict::Te,
+                    score: 0.,
+                    time: first_result.time,
+                    memory: first_result.memory,
+                    output: first_output,
+                    truncated: first_truncated,
+                    message: Arc::from(
+                        format!(
+                            "transformer_error: {transformer_error}\n 'isolate': {}",
+                            transformer_result.status_message.as_deref().unwrap_or("")
+                        )
+                        .as_str(),
+                    ),
+                    comment: None,
+                    transcript: None,
+                });
+            }
+            TARGET_SECOND_INPUT_PATH
+        } else {
+            log::debug!("({log_state}) transformer not founded, passing first output as is");
+            TARGET_FIRST_OUTPUT_PATH
         };
 
-        Ok(test::Result {
+        self.second_sandbox
+            .write_into_box(
+                &mut self.first_sandbox.read_from_box(second_input_src).await?,
+                TARGET_SECOND_INPUT_PATH,
+            )
+            .await?;
+        self.second_sandbox
+            .write_into_box(
+                &mut File::open(&*src_solution_path).await?,
+                TARGET_SOLUTION_PATH,
+            )
+            .await?;
+
+        let second_result = match self
+            .run_solution(
+                &self.second_sandbox,
+                TARGET_SECOND_INPUT_PATH,
+                TARGET_OUTPUT_PATH,
+            )
+            .await
+        {
+            Ok(res) => res,
+            Err(e) => {
+                log::error!("({log_state}) second solution run error: {e:?}");
+                return Err(e);
+            }
+        };
+
+        let (output, truncated) = payload::read_truncated(
+            self.second_sandbox
+                .read_from_box(TARGET_OUTPUT_PATH)
+                .await?,
+            self.data_limit,
+        )
+        .await?;
+        let output: Arc<str> = Arc::from(output);
+        let time = f64::max(first_result.time, second_result.time);
+        let memory = u64::max(first_result.memory, second_result.memory);
+
+        if let Some(verdict) = test::Verdict::from_run_status(second_result.status) {
+            return Ok(test::Result {
+                verdict,
+                score: 0.,
+                time,
+                memory,
+                output,
+                truncated,
+                message: Arc::from(
+                    format!(
+                        "second run: ISOLATE: {}",
+                        second_result.status_message.unwrap_or(Box::from("-"))
+                    )
+                    .as_str(),
+                ),
+                comment: None,
+                transcript: None,
+            });
+        }
+
+        // original input is given to the second box only after the second run
+        let mut files = vec![(File::open(&*src_input_path).await?, TARGET_INPUT_PATH)];
+        if self.checker == submission::Checker::Package {
+            files.push((File::open(&*src_checker_path).await?, TARGET_CHECKER_PATH));
+        }
+        Arc::clone(&self.second_sandbox)
+            .write_group_into_box(
+                files
+                    .into_iter()
+                    .map(|(from, to)| (from, Box::from(to)))
+                    .collect(),
+            )
+            .await?;
+        if let Ok(mut correct) = File::open(&*src_correct_path).await {
+            self.second_sandbox
+                .write_into_box(&mut correct, TARGET_CORRECT_PATH)
+                .await?;
+        } else {
+            log::debug!("({log_state}) correct file not founded");
+        }
+
+        let (verdict, score, message, comment) = check(
+            Arc::clone(&self.second_sandbox),
+            self.checker,
+            self.checker_limits,
+            &log_state,
+        )
+        .await?;
+
+        let result = test::Result {
             verdict,
+            score,
             message: Arc::from(message),
+            comment,
+            transcript: None,
 
             output,
-            memory: solution_result.memory,
-            time: solution_result.time,
-        })
+            truncated,
+            memory,
+            time,
+        };
+
+        log::info!("({log_state}) judgement result:\n{result:#?}");
+
+        Ok(result)
     }
 }
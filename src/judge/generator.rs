@@ -0,0 +1,282 @@
+use std::{collections::HashMap, sync::Arc};
+
+use sha2::{Digest, Sha256};
+use tokio::fs::{File, create_dir_all, rename};
+
+use super::{
+    Lang, OperatorError, Service,
+    api::submission::{self, Task},
+    cache, path_from,
+    standard::{CORRECT_DIR, CORRECT_EXT},
+    tests_location,
+};
+use crate::{
+    LogState, Result,
+    sandbox::{MaybeLimited::*, RunStatus},
+};
+
+const GENERATORS_DIR: &str = "generators";
+const TESTS_CACHE_DIR: &str = "tests";
+
+const TARGET_PROGRAM_PATH: &str = "program.out";
+const TARGET_INPUT_PATH: &str = "in.txt";
+const TARGET_OUTPUT_PATH: &str = "out.txt";
+const TARGET_ERROR_PATH: &str = "err.txt";
+
+/// Hex encoded sha256 of `parts`
+pub fn hash(parts: &[&[u8]]) -> String {
+    let mut hasher = Sha256::new();
+    for part in parts {
+        hasher.update((part.len() as u64).to_le_bytes());
+        hasher.update(part);
+    }
+    hasher
+        .finalize()
+        .iter()
+        .map(|b| format!("{b:02x}"))
+        .collect()
+}
+
+/// Generator or model solution from package, compiled on first use
+struct Program {
+    lang: Lang,
+    src_path: Box<str>,
+    needs_compilation: bool,
+    compiled_path: Option<Box<str>>,
+    hash: String,
+}
+
+impl Program {
+    async fn new(lang: Lang, src_path: Box<str>, needs_compilation: bool) -> Result<Program> {
+        let hash = hash(&[&tokio::fs::read(&*src_path).await?]);
+        Ok(Program {
+            lang,
+            src_path,
+            needs_compilation,
+            compiled_path: None,
+            hash,
+        })
+    }
+
+    async fn find_generator(work_dir: &str, name: &str) -> Result<Program> {
+        let dir = format!("{work_dir}/{GENERATORS_DIR}");
+        for (ext, lang, needs_compilation) in [
+            ("out", Lang::Gpp, false),
+            ("cpp", Lang::Gpp, true),
+            ("py", Lang::Python, true),
+        ] {
+            let path = path_from(&dir, name, Some(ext));
+            if tokio::fs::try_exists(&*path).await? {
+                return Program::new(lang, path, needs_compilation).await;
+            }
+        }
+        Err(
+            OperatorError(format!("generator '{name}' not found in '{GENERATORS_DIR}'").into())
+                .into(),
+        )
+    }
+}
+
+impl Service {
+    /// Puts generated tests and model answers into work directory, using tests cache
+    pub(super) async fn generate_tests(
+        &self,
+        work_dir: &str,
+        task: &Task,
+        generation: &submission::Generation,
+    ) -> Result<()> {
+        let (tests_dir, tests_ext) = tests_location(&task.r#type);
+        let tests_dir = format!("{work_dir}/{tests_dir}");
+        let cache_dir = format!("{}/{TESTS_CACHE_DIR}", self.cache_dir);
+        create_dir_all(&tests_dir).await?;
+        create_dir_all(&cache_dir).await?;
+
+        let mut generators = HashMap::<&str, Program>::new();
+        let mut tests: Vec<_> = generation.tests.iter().collect();
+        tests.sort_by_key(|(test_id, _)| **test_id);
+        for (&test_id, command) in tests {
+            let log_state = LogState::new().push("test", &*format!("{test_id}"));
+            let mut words = command.split_whitespace();
+            let Some(name) = words.next() else {
+                return Err(OperatorError(
+                    format!("test {test_id}: empty generator command").into(),
+                )
+                .into());
+            };
+            let args: Vec<&str> = words.collect();
+
+            if !generators.contains_key(name) {
+                generators.insert(name, Program::find_generator(work_dir, name).await?);
+            }
+            let generator = generators.get_mut(name).unwrap();
+
+            let cached_path = format!(
+                "{cache_dir}/{}.txt",
+                hash(&[generator.hash.as_bytes(), args.join(" ").as_bytes()])
+            );
+            let test_path = path_from(&tests_dir, &format!("{test_id}"), tests_ext);
+            if cache::take(&cached_path, &test_path).await? {
+                log::trace!("({log_state}) generated test found in cache");
+            } else {
+                log::debug!("({log_state}) generating: {command}");
+                if let Some(message) = self
+                    .run_program(work_dir, generator, &args, None, &test_path)
+                    .await?
+                {
+                    return Err(OperatorError(
+                        format!("test {test_id}: generator '{command}' failed: {message}").into(),
+                    )
+                    .into());
+                }
+                cache::put(&test_path, &cached_path).await?;
+            }
+        }
+        cache::evict(&cache_dir, self.config.tests_cache_size).await?;
+
+        let Some(model) = &generation.model else {
+            return Ok(());
+        };
+        if let submission::Type::Interactive = task.r#type {
+            log::debug!("model solution is ignored for interactive task");
+            return Ok(());
+        }
+
+        let correct_dir = format!("{work_dir}/{CORRECT_DIR}");
+        create_dir_all(&correct_dir).await?;
+        let mut model = Program::new(
+            model.lang,
+            format!("{work_dir}/{}", model.source).into_boxed_str(),
+            true,
+        )
+        .await
+        .map_err(|e| OperatorError(format!("model solution '{}': {e}", model.source).into()))?;
+
+        for group in &task.groups {
+            for test_id in group.range.0..=group.range.1 {
+                let correct_path = path_from(&correct_dir, &format!("{test_id}"), CORRECT_EXT);
+                if tokio::fs::try_exists(&*correct_path).await? {
+                    continue;
+                }
+                let log_state = LogState::new().push("test", &*format!("{test_id}"));
+                let input_path = path_from(&tests_dir, &format!("{test_id}"), tests_ext);
+                let Ok(input) = tokio::fs::read(&*input_path).await else {
+                    return Err(
+                        OperatorError(format!("test {test_id}: input not found").into()).into(),
+                    );
+                };
+
+                let cached_path =
+                    format!("{cache_dir}/{}.txt", hash(&[model.hash.as_bytes(), &input]));
+                if cache::take(&cached_path, &correct_path).await? {
+                    log::trace!("({log_state}) model answer found in cache");
+                } else {
+                    log::debug!("({log_state}) running model solution");
+                    if let Some(message) = self
+                        .run_program(work_dir, &mut model, &[], Some(&input_path), &correct_path)
+                        .await?
+                    {
+                        return Err(OperatorError(
+                            format!("test {test_id}: model solution failed: {message}").into(),
+                        )
+                        .into());
+                    }
+                    cache::put(&correct_path, &cached_path).await?;
+                }
+            }
+        }
+        cache::evict(&cache_dir, self.config.tests_cache_size).await
+    }
+
+    /// Runs model solution on `input_path` and saves its answer to `dst_path`,
+    /// returns error message if model solution failed
+    pub(super) async fn run_model(
+        &self,
+        work_dir: &str,
+        model: &submission::Model,
+        input_path: &str,
+        dst_path: &str,
+    ) -> Result<Option<String>> {
+        let mut model = Program::new(
+            model.lang,
+            format!("{work_dir}/{}", model.source).into_boxed_str(),
+            true,
+        )
+        .await
+        .map_err(|e| OperatorError(format!("model solution '{}': {e}", model.source).into()))?;
+        self.run_program(work_dir, &mut model, &[], Some(input_path), dst_path)
+            .await
+    }
+
+    /// Runs `program` once and saves its stdout to `dst_path`,
+    /// returns error message if program cannot be compiled or failed
+    async fn run_program(
+        &self,
+        work_dir: &str,
+        program: &mut Program,
+        args: &[&str],
+        stdin: Option<&str>,
+        dst_path: &str,
+    ) -> Result<Option<String>> {
+        if program.needs_compilation && program.compiled_path.is_none() {
+            let compiled_path = format!("{}.out", program.src_path).into_boxed_str();
+            let report = self
+                .compile(
+                    work_dir,
+                    program.lang,
+                    &program.src_path,
+                    None,
+                    &compiled_path,
+                )
+                .await?;
+            if report.exit_code != Some(0) {
+                return Ok(Some(format!("compilation error: {}", report.stderr)));
+            }
+            program.compiled_path = Some(compiled_path);
+        }
+        let exe_path = program
+            .compiled_path
+            .as_deref()
+            .unwrap_or(&program.src_path);
+
+        let sandbox = Arc::new(Arc::clone(&self.sandboxes).initialize_sandbox().await?);
+        sandbox
+            .write_into_box(&mut File::open(exe_path).await?, TARGET_PROGRAM_PATH)
+            .await?;
+
+        let mut cmd = program.lang.command_to_run(TARGET_PROGRAM_PATH);
+        cmd.args(args)
+            .count_files(Unlimited)
+            .count_process(Unlimited)
+            .stdout(TARGET_OUTPUT_PATH)
+            .stderr(TARGET_ERROR_PATH);
+        if let Some(stdin) = stdin {
+            sandbox
+                .write_into_box(&mut File::open(stdin).await?, TARGET_INPUT_PATH)
+                .await?;
+            cmd.stdin(TARGET_INPUT_PATH);
+        }
+
+        let result = sandbox.run(&cmd).await?;
+        if result.status != RunStatus::Ok {
+            let error = sandbox
+                .read_string_from_box(TARGET_ERROR_PATH)
+                .await
+                .unwrap_or("-".to_string());
+            return Ok(Some(format!(
+                "{error}\n 'isolate': {}",
+                result.status_message.as_deref().unwrap_or("")
+            )));
+        }
+
+        // `dst_path` appears only when it is completely written
+        let tmp_path = cache::tmp_path(dst_path);
+        let mut file = File::create(&tmp_path).await?;
+        tokio::io::copy(
+            &mut sandbox.read_from_box(TARGET_OUTPUT_PATH).await?,
+            &mut file,
+        )
+        .await?;
+        rename(&tmp_path, dst_path).await?;
+        Ok(None)
+    }
+}
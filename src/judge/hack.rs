@@ -0,0 +1,184 @@
+use std::sync::Arc;
+
+use tar_archive_rs as archive;
+use tokio::{
+    fs::{File, copy, create_dir_all},
+    io::AsyncRead,
+};
+
+use super::{
+    Lang, OperatorError, SOLUTION_NAME, Service,
+    api::{hack, submission, test},
+    path_from, payload, read_task,
+    standard::{CORRECT_DIR, CORRECT_EXT},
+    tests_location, validator,
+};
+use crate::{LogState, Result, prelude::*};
+
+const HACK_DIR: &str = "hack";
+const HACK_SOLUTION_NAME: &str = "solution";
+const HACK_INPUT_NAME: &str = "input";
+
+impl Service {
+    /// Judges target solution on hack input as on one more test of the task:
+    /// input is validated (as a test of `group`, if set), answered by the model solution
+    /// and checked by the task checker. `hack` contains `solution` source and `input`
+    pub async fn hack<R: Unpin + AsyncRead, H: Unpin + AsyncRead>(
+        self: Arc<Self>,
+        submission: &str,
+        mut package: archive::Archive<R>,
+        lang: Lang,
+        group: Option<usize>,
+        mut hack: archive::Archive<H>,
+    ) -> Result<hack::Result> {
+        let (_permit, job) = self.start_job(submission).await?;
+        let work_dir = &*job.work_dir;
+        package.unpack(work_dir).await?;
+        let hack_dir = format!("{work_dir}/{HACK_DIR}");
+        create_dir_all(&hack_dir).await?;
+        hack.unpack(&hack_dir).await?;
+        copy(
+            format!("{hack_dir}/{HACK_SOLUTION_NAME}"),
+            &*path_from(work_dir, SOLUTION_NAME, None),
+        )
+        .await
+        .context("hack solution not found")?;
+
+        let mut task = read_task(work_dir).await?;
+        task.lang = lang;
+        let task = Arc::new(task);
+        if let submission::Type::OutputOnly = task.r#type {
+            return Err(OperatorError(Box::from("output only tasks cannot be hacked")).into());
+        }
+        if let Some(group) = group
+            && group >= task.groups.len()
+        {
+            return Err(
+                OperatorError(format!("hack group {group} not found").into_boxed_str()).into(),
+            );
+        }
+
+        // hack test gets the number after the last test of the task
+        let test_number = task
+            .groups
+            .iter()
+            .map(|group| group.range.1)
+            .max()
+            .unwrap_or(0);
+        let log_state = LogState::new()
+            .push("submission", submission)
+            .push("test", &*format!("{test_number}"));
+        let (tests_dir, tests_ext) = tests_location(&task.r#type);
+        create_dir_all(format!("{work_dir}/{tests_dir}")).await?;
+        let input_path = path_from(
+            &format!("{work_dir}/{tests_dir}"),
+            &format!("{}", test_number + 1),
+            tests_ext,
+        );
+        copy(format!("{hack_dir}/{HACK_INPUT_NAME}"), &*input_path)
+            .await
+            .context("hack input not found")?;
+
+        if let Some(message) =
+            validator::validate_input(Arc::clone(&self.sandboxes), work_dir, &input_path, group)
+                .await
+                .context("hack validating")?
+        {
+            log::info!("({log_state}) hack is invalid");
+            return Ok(hack::Result::Invalid(message.into_boxed_str()));
+        }
+
+        let correct_dir = format!("{work_dir}/{CORRECT_DIR}");
+        create_dir_all(&correct_dir).await?;
+        let correct_path = path_from(&correct_dir, &format!("{}", test_number + 1), CORRECT_EXT);
+        match task
+            .generation
+            .as_ref()
+            .and_then(|generation| generation.model.as_ref())
+        {
+            Some(model) => {
+                if let Some(message) = self
+                    .run_model(work_dir, model, &input_path, &correct_path)
+                    .await?
+                {
+                    return Err(OperatorError(
+                        format!("model solution failed on hack: {message}").into(),
+                    )
+                    .into());
+                }
+            }
+            None if matches!(task.r#type, submission::Type::Interactive) => {
+                log::debug!("({log_state}) interactive hack is judged without model answer");
+            }
+            None => {
+                return Err(
+                    OperatorError(Box::from("task has no model solution to answer hacks")).into(),
+                );
+            }
+        }
+
+        let compilation = self
+            .compile_solution(work_dir, task.lang, task.grader.as_ref())
+            .await
+            .context("solution compiling")?;
+        match compilation.exit_code {
+            Some(0) => (),
+            Some(_) => return Ok(hack::Result::Ce(compilation)),
+            None => return Ok(hack::Result::Te(compilation.stderr)),
+        }
+        if let submission::Type::Interactive = task.r#type {
+            self.compile_instances(work_dir, &task)
+                .await
+                .context("instances compiling")?;
+        }
+
+        let limits = match group {
+            Some(group) => task.test_limits(group, test_number + 1),
+            None => task.limits,
+        };
+        let test = self
+            .prepare(
+                work_dir,
+                Arc::clone(&task),
+                self.lang_limits(&task, limits),
+                test_number,
+                Arc::clone(&log_state),
+            )
+            .await
+            .context("enviroment preparing")?
+            .run()
+            .await
+            .context("enviroment running")?;
+        // only contestant verdicts count, failures of judging are not blamed on the solution
+        let successful = match test.verdict {
+            test::Verdict::Ok => false,
+            test::Verdict::Wa
+            | test::Verdict::Pc
+            | test::Verdict::Pe
+            | test::Verdict::Ml
+            | test::Verdict::Tl
+            | test::Verdict::Re
+            | test::Verdict::Sl => true,
+            test::Verdict::Te | test::Verdict::Fl | test::Verdict::Ce | test::Verdict::Sk => {
+                log::warn!("({log_state}) hack is not judged: {}", test.verdict);
+                return Ok(hack::Result::Te(
+                    format!("{}: {}", test.verdict, test.message).into_boxed_str(),
+                ));
+            }
+        };
+        log::info!("({log_state}) hack is successful: {successful}");
+
+        let answer = match File::open(&*correct_path).await {
+            Ok(file) => payload::read_truncated(file, self.config.data.limit)
+                .await
+                .map(|(text, _)| text)
+                .unwrap_or_default(),
+            Err(_) => String::new(),
+        };
+        Ok(hack::Result::Ok {
+            successful,
+            test,
+            answer: answer.into_boxed_str(),
+        })
+    }
+}
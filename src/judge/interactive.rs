@@ -1,38 +1,54 @@
-use std::sync::Arc;
+use std::{
+    sync::{Arc, Mutex},
+    time::Duration,
+};
 
 use crate::channel::Channel;
 use async_trait::async_trait;
-use tokio::{fs::File, io::AsyncReadExt as _};
+use tokio::fs::File;
 
 use super::{
-    CHANNEL_DIR, Lang, SOLUTION_EXT, SOLUTION_NAME,
-    api::{submission, test},
-    path_from,
+    CHANNEL_DIR, Lang, OperatorError, SOLUTION_EXT, SOLUTION_NAME, Service,
+    api::{
+        submission::{self, Task},
+        test,
+    },
+    path_from, payload, standard,
+    transcript::{self, Transcript},
 };
 use crate::{
     LogState, Result,
-    sandbox::{self, MaybeLimited, RunStatus},
+    sandbox::{self, Command, MaybeLimited, RunStatus},
 };
 
-const TEST_DIR: &str = "test";
-const TEST_EXT: Option<&str> = Some("txt");
+pub(super) const TEST_DIR: &str = "test";
+pub(super) const TEST_EXT: Option<&str> = Some("txt");
 
-const INTERACTOR_NAME: &str = "interactor";
-const INTERACTOR_EXT: Option<&str> = Some("out");
+pub(super) const INTERACTOR_NAME: &str = "interactor";
+pub(super) const INTERACTOR_EXT: Option<&str> = Some("out");
 
 const TARGET_TEST_PATH: &str = "test.txt";
 const TARGET_INTERACTOR_OUTPUT_PATH: &str = "interactor_out.txt";
 const TARGET_INTERACTOR_ERROR_PATH: &str = "interactor_err.txt";
 
+const INSTANCES_DIR: &str = "instances";
+const PROXY_DRAIN_TIMEOUT: Duration = Duration::from_secs(1);
+
 const TARGET_INTERACTOR_PATH: &str = "interactor.out";
 const TARGET_SOLUTION_PATH: &str = "solution.out";
 
 pub struct Enviroment {
-    sandbox: Arc<sandbox::Sandbox>,
+    sandboxes: Box<[Arc<sandbox::Sandbox>]>,
     interactor_sandbox: Arc<sandbox::Sandbox>,
+    instances: Box<[submission::Instance]>,
     limits: submission::Limits,
     lang: Lang,
+    checker: submission::Checker,
+    checker_limits: submission::LimitsOverride,
+    interactor_limits: submission::LimitsOverride,
+    transcript: Option<submission::TranscriptConfig>,
 
+    data_limit: usize,
     work_dir: Box<str>,
     test_id: usize,
     log_state: Arc<LogState>,
@@ -42,31 +58,199 @@ pub async fn prepare(
     sandboxes: Arc<sandbox::Service>,
     lang: Lang,
     limits: submission::Limits,
+    checker: submission::Checker,
+    checker_limits: submission::LimitsOverride,
+    interactor_limits: submission::LimitsOverride,
+    transcript: Option<submission::TranscriptConfig>,
+    instances: Box<[submission::Instance]>,
+    data_limit: usize,
     work_dir: Box<str>,
 
     test_id: usize,
     log_state: Arc<LogState>,
 ) -> Result<Enviroment> {
-    let sandbox = Arc::new(Arc::clone(&sandboxes).initialize_sandbox().await?);
-    let interactor_sandbox = Arc::new(sandboxes.initialize_sandbox().await?);
+    let mut solution_sandboxes = sandboxes
+        .initialize_sandboxes(instances.len() + 1)
+        .await?
+        .into_iter()
+        .map(Arc::new)
+        .collect::<Vec<_>>();
+    let interactor_sandbox = solution_sandboxes.pop().unwrap();
+    let mut log_state = log_state;
+    for (i, sandbox) in solution_sandboxes.iter().enumerate() {
+        log_state = log_state.push(
+            &format!("solution_{i}_box_id"),
+            &*format!("{}", sandbox.id()),
+        );
+    }
 
-    let log_state = log_state.push("solution_box_id", &*format!("{}", sandbox.id()));
     let log_state = log_state.push(
         "interactor_box_id",
         &*format!("{}", interactor_sandbox.id()),
     );
 
     Ok(Enviroment {
-        sandbox,
+        sandboxes: solution_sandboxes.into_boxed_slice(),
         interactor_sandbox,
+        instances,
         lang,
         limits,
+        checker,
+        checker_limits,
+        interactor_limits,
+        transcript,
+        data_limit,
         work_dir,
         test_id,
         log_state,
     })
 }
 
+/// Who decides the verdict of interactive test
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Attribution {
+    Interactor,
+    Instance, // the first failed solution instance
+    Checker,  // if there is one, otherwise the interactor
+}
+
+/// __polygon__ rules: interactor `FL` and `WA` are final. Otherwise a failed instance gives
+/// its verdict, as the interactor reports its crash as `PE` or unexpected EOF.
+/// Otherwise the interactor verdict is used, accepted interaction goes to the checker
+fn attribute(interactor: test::Verdict, instance_failed: bool) -> Attribution {
+    match (interactor, instance_failed) {
+        (test::Verdict::Fl | test::Verdict::Wa, _) => Attribution::Interactor,
+        (_, true) => Attribution::Instance,
+        (test::Verdict::Ok, false) => Attribution::Checker,
+        (_, false) => Attribution::Interactor,
+    }
+}
+
+/// Compiled `program` of instance `i`
+fn instance_program_path(work_dir: &str, i: usize) -> Box<str> {
+    path_from(
+        &format!("{work_dir}/{INSTANCES_DIR}"),
+        &format!("{i}"),
+        SOLUTION_EXT,
+    )
+}
+
+impl Service {
+    /// Compiles `program` of every instance which has one, failure is a package problem
+    pub(super) async fn compile_instances(&self, work_dir: &str, task: &Task) -> Result<()> {
+        for (i, instance) in task.instances.iter().enumerate() {
+            let Some(program) = &instance.program else {
+                continue;
+            };
+            tokio::fs::create_dir_all(format!("{work_dir}/{INSTANCES_DIR}")).await?;
+            let report = self
+                .compile(
+                    work_dir,
+                    instance.lang.unwrap_or(task.lang),
+                    &format!("{work_dir}/{program}"),
+                    None,
+                    &instance_program_path(work_dir, i),
+                )
+                .await?;
+            if report.exit_code != Some(0) {
+                return Err(OperatorError(
+                    format!(
+                        "instance {i}: program '{program}' compilation failed:\n{}",
+                        report.stderr
+                    )
+                    .into(),
+                )
+                .into());
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Creates FIFO, which is kept opened by `keepers`, so it can be opened without blocking
+async fn kept_channel(keepers: &mut Vec<File>) -> Result<Channel> {
+    let channel = Channel::new(CHANNEL_DIR).await?;
+    keepers.push(
+        File::options()
+            .read(true)
+            .write(true)
+            .open(&*channel.0)
+            .await?,
+    );
+    Ok(channel)
+}
+
+impl Enviroment {
+    /// Checker after interaction is optional: builtin or `checker.out` in package
+    async fn has_checker(&self) -> Result<bool> {
+        Ok(match self.checker {
+            submission::Checker::Builtin(_) => true,
+            submission::Checker::Package => {
+                tokio::fs::try_exists(&*path_from(
+                    &self.work_dir,
+                    standard::CHECKER_NAME,
+                    standard::CHECKER_EXT,
+                ))
+                .await?
+            }
+        })
+    }
+
+    /// Runs checker in the interactor box on the test, interactor output and correct answer
+    async fn check_output(&self) -> Result<(test::Verdict, f64, String, Option<test::Comment>)> {
+        let sandbox = &self.interactor_sandbox;
+        let log_state = self.log_state.push("stage", "checker");
+
+        let mut test_file = File::open(&*path_from(
+            &format!("{}/{TEST_DIR}", self.work_dir),
+            &format!("{}", self.test_id + 1),
+            TEST_EXT,
+        ))
+        .await?;
+        sandbox
+            .write_into_box(&mut test_file, standard::TARGET_INPUT_PATH)
+            .await?;
+        let mut output_file = sandbox.read_from_box(TARGET_INTERACTOR_OUTPUT_PATH).await?;
+        sandbox
+            .write_into_box(&mut output_file, standard::TARGET_OUTPUT_PATH)
+            .await?;
+        if let Ok(mut correct) = File::open(&*path_from(
+            &format!("{}/{}", self.work_dir, standard::CORRECT_DIR),
+            &format!("{}", self.test_id + 1),
+            standard::CORRECT_EXT,
+        ))
+        .await
+        {
+            sandbox
+                .write_into_box(&mut correct, standard::TARGET_CORRECT_PATH)
+                .await?;
+        } else {
+            log::debug!("({log_state}) correct file not founded");
+        }
+        if self.checker == submission::Checker::Package {
+            sandbox
+                .write_into_box(
+                    &mut File::open(&*path_from(
+                        &self.work_dir,
+                        standard::CHECKER_NAME,
+                        standard::CHECKER_EXT,
+                    ))
+                    .await?,
+                    standard::TARGET_CHECKER_PATH,
+                )
+                .await?;
+        }
+
+        standard::check(
+            Arc::clone(sandbox),
+            self.checker,
+            self.checker_limits,
+            &log_state,
+        )
+        .await
+    }
+}
+
 #[async_trait]
 impl super::Enviroment for Enviroment {
     async fn run(self: Box<Self>) -> Result<test::Result> {
@@ -95,75 +279,131 @@ impl super::Enviroment for Enviroment {
                 .collect(),
             )
             .await?;
-        self.sandbox
-            .write_into_box(
-                &mut File::open(&*src_solution_path).await?,
-                TARGET_SOLUTION_PATH,
-            )
-            .await?;
-
-        let solution_input_channel = Channel::new(CHANNEL_DIR).await?;
-        let solution_output_channel = Channel::new(CHANNEL_DIR).await?;
-
-        let _solution_output_keeper = File::options()
-            .read(true)
-            .write(true)
-            .open(&*solution_output_channel.0)
-            .await?;
+        for (i, (sandbox, instance)) in self.sandboxes.iter().zip(&self.instances).enumerate() {
+            let src_path = match instance.program {
+                Some(_) => instance_program_path(&self.work_dir, i),
+                None => src_solution_path.clone(),
+            };
+            sandbox
+                .write_into_box(&mut File::open(&*src_path).await?, TARGET_SOLUTION_PATH)
+                .await?;
+        }
 
-        let _solution_input_keeper = File::options()
-            .read(true)
-            .write(true)
-            .open(&*solution_input_channel.0)
-            .await?;
+        // (solution input, solution output) for every instance
+        let mut channels = vec![];
+        // (to solution, from solution) for every instance, differ from solution channels with proxies
+        let mut channel_paths = vec![];
+        let mut channel_keepers = vec![];
+        let mut _proxy_channels = vec![];
+        let mut proxies = vec![];
+        let transcript = self
+            .transcript
+            .map(|config| Arc::new(Mutex::new(Transcript::new(config.limit))));
+        for i in 0..self.instances.len() {
+            let solution_input_channel = kept_channel(&mut channel_keepers).await?;
+            let solution_output_channel = kept_channel(&mut channel_keepers).await?;
+
+            if let Some(transcript) = &transcript {
+                let to_solution_channel = kept_channel(&mut channel_keepers).await?;
+                let from_solution_channel = kept_channel(&mut channel_keepers).await?;
+                proxies.push(tokio::spawn(transcript::proxy(
+                    to_solution_channel.0.clone(),
+                    solution_input_channel.0.clone(),
+                    format!("interactor -> {i}").into(),
+                    Arc::clone(transcript),
+                )));
+                proxies.push(tokio::spawn(transcript::proxy(
+                    solution_output_channel.0.clone(),
+                    from_solution_channel.0.clone(),
+                    format!("{i} -> interactor").into(),
+                    Arc::clone(transcript),
+                )));
+                channel_paths.push((
+                    to_solution_channel.0.clone(),
+                    from_solution_channel.0.clone(),
+                ));
+                _proxy_channels.push((to_solution_channel, from_solution_channel));
+            } else {
+                channel_paths.push((
+                    solution_input_channel.0.clone(),
+                    solution_output_channel.0.clone(),
+                ));
+            }
+            channels.push((solution_input_channel, solution_output_channel));
+        }
 
         let interactor_sandbox_clone = Arc::clone(&self.interactor_sandbox);
         let lang = self.lang;
         let time_limit = self.limits.time;
         let real_time_limit = self.limits.real_time;
-        let solution_output_channel_path = solution_output_channel.0.clone();
-        let solution_input_channel_path = solution_input_channel.0.clone();
+        // interactor keeps solution time limits, unless its own are set
+        let interactor_limits = submission::LimitsOverride {
+            time: self.interactor_limits.time.or(Some(time_limit)),
+            real_time: self.interactor_limits.real_time.or(Some(real_time_limit)),
+            ..self.interactor_limits
+        };
         let interactor_handler = tokio::spawn(async move {
-            let mut cmd = lang.command_to_run(TARGET_INTERACTOR_PATH);
+            // interactor is a native binary of the package, as the checker
+            let mut cmd = Command::new(format!("./{TARGET_INTERACTOR_PATH}"));
             cmd.args([TARGET_TEST_PATH, TARGET_INTERACTOR_OUTPUT_PATH])
-                .time(MaybeLimited::Limited(time_limit))
-                .real_time(MaybeLimited::Limited(real_time_limit))
                 .count_process(MaybeLimited::Unlimited)
                 .open_dir(CHANNEL_DIR)
-                .stdin(solution_output_channel_path)
-                .stdout(solution_input_channel_path)
                 .stderr(TARGET_INTERACTOR_ERROR_PATH);
+            standard::limit_command(&mut cmd, &interactor_limits);
+            if let [(input, output)] = &*channel_paths {
+                cmd.stdin(output).stdout(input);
+            } else {
+                // manager gets (to solution, from solution) pairs for every instance
+                cmd.count_files(MaybeLimited::Unlimited);
+                for (input, output) in channel_paths {
+                    cmd.args([input, output]);
+                }
+            }
             interactor_sandbox_clone.run(&cmd).await
         });
 
-        let sandbox_clone = Arc::clone(&self.sandbox);
         let memory_limit = self.limits.memory;
         let stack_limit = self.limits.stack;
-        let solution_input_channel_path = solution_input_channel.0.clone();
-        let solution_output_channel_path = solution_output_channel.0.clone();
-
-        let solution_handler = tokio::spawn(async move {
-            let mut cmd = lang.command_to_run(TARGET_SOLUTION_PATH);
-            cmd.time(MaybeLimited::Limited(time_limit))
-                .memory(MaybeLimited::Limited(memory_limit))
-                .real_time(MaybeLimited::Limited(real_time_limit));
-            if let Some(stack) = stack_limit {
-                cmd.stack(MaybeLimited::Limited(stack));
-            }
-            cmd.count_process(MaybeLimited::Limited(1))
-                .open_dir(CHANNEL_DIR)
-                .stdin(&*solution_input_channel_path)
-                .stdout(&*solution_output_channel_path);
-            sandbox_clone.run(&cmd).await
-        });
+        let mut solution_handlers = vec![];
+        for ((sandbox, instance), (input, output)) in
+            self.sandboxes.iter().zip(&self.instances).zip(&channels)
+        {
+            let sandbox_clone = Arc::clone(sandbox);
+            let args = instance.args.clone();
+            let lang = match instance.program {
+                Some(_) => instance.lang.unwrap_or(lang),
+                None => lang,
+            };
+            let solution_input_channel_path = input.0.clone();
+            let solution_output_channel_path = output.0.clone();
+
+            solution_handlers.push(tokio::spawn(async move {
+                let mut cmd = lang.command_to_run(TARGET_SOLUTION_PATH);
+                cmd.args(args)
+                    .time(MaybeLimited::Limited(time_limit))
+                    .memory(MaybeLimited::Limited(memory_limit))
+                    .real_time(MaybeLimited::Limited(real_time_limit));
+                if let Some(stack) = stack_limit {
+                    cmd.stack(MaybeLimited::Limited(stack));
+                }
+                cmd.count_process(MaybeLimited::Limited(1))
+                    .open_dir(CHANNEL_DIR)
+                    .stdin(&*solution_input_channel_path)
+                    .stdout(&*solution_output_channel_path);
+                sandbox_clone.run(&cmd).await
+            }));
+        }
 
-        let solution_result = match solution_handler.await? {
-            Ok(res) => res,
-            Err(e) => {
-                log::error!("({log_state}) solution run error: {e:?}");
-                return Err(e);
-            }
-        };
+        let mut solution_results = vec![];
+        for solution_handler in solution_handlers {
+            solution_results.push(match solution_handler.await? {
+                Ok(res) => res,
+                Err(e) => {
+                    log::error!("({log_state}) solution run error: {e:?}");
+                    return Err(e);
+                }
+            });
+        }
 
         let interactor_result = match interactor_handler.await? {
             Ok(res) => res,
@@ -173,88 +413,115 @@ impl super::Enviroment for Enviroment {
             }
         };
 
-        let interactor_output: Arc<str> = Arc::from(&*if let Ok(mut file) = self
+        // proxies read the rest of data and get EOF, when nobody keeps their FIFOs opened
+        drop(channel_keepers);
+        for mut proxy in proxies {
+            if tokio::time::timeout(PROXY_DRAIN_TIMEOUT, &mut proxy)
+                .await
+                .is_err()
+            {
+                log::warn!(
+                    "({log_state}) transcript proxy is not drained, transcript may be incomplete"
+                );
+                proxy.abort();
+            }
+        }
+        let transcript: Option<Arc<str>> =
+            transcript.map(|transcript| Arc::from(transcript.lock().unwrap().text()));
+
+        let (interactor_output, truncated) = if let Ok(file) = self
             .interactor_sandbox
             .read_from_box(TARGET_INTERACTOR_OUTPUT_PATH)
             .await
         {
-            let mut output_error = String::new();
-            file.read_to_string(&mut output_error).await?;
-            output_error
+            payload::read_truncated(file, self.data_limit).await?
         } else {
-            String::new()
-        });
+            (String::new(), false)
+        };
+        let interactor_output: Arc<str> = Arc::from(interactor_output);
+
+        let interactor_error: Arc<str> = Arc::from(
+            if let Ok(file) = self
+                .interactor_sandbox
+                .read_from_box(TARGET_INTERACTOR_ERROR_PATH)
+                .await
+            {
+                payload::read_truncated(file, self.data_limit).await?.0
+            } else {
+                String::new()
+            },
+        );
 
-        let interactor_error: Arc<str> = Arc::from(&*if let Ok(mut file) = self
-            .interactor_sandbox
-            .read_from_box(TARGET_INTERACTOR_ERROR_PATH)
-            .await
-        {
-            let mut interactor_error = String::new();
-            file.read_to_string(&mut interactor_error).await?;
-            interactor_error
-        } else {
-            String::new()
+        let time = solution_results
+            .iter()
+            .map(|result| result.time)
+            .fold(0., f64::max);
+        let memory = solution_results
+            .iter()
+            .map(|result| result.memory)
+            .max()
+            .unwrap_or(0);
+
+        let failed_instance = solution_results.iter().enumerate().find_map(|(i, result)| {
+            Some((
+                i,
+                test::Verdict::from_run_status(result.status)?,
+                result.status_message.as_deref().unwrap_or("-"),
+            ))
         });
-
-        if let Some(verdict) = test::Verdict::from_run_status(solution_result.status) {
-            return Ok(test::Result {
-                verdict,
-                time: solution_result.time,
-                memory: solution_result.memory,
-                output: interactor_output,
-                message: Arc::from(
-                    format!(
-                        "ISOLATE: {}\nINTERACTOR_ERRORS: {}",
-                        solution_result.status_message.unwrap_or(Box::from("-")),
-                        interactor_error,
-                    )
-                    .as_str(),
-                ),
-            });
+        let mut interactor_message = format!(
+            "interactor_output: {interactor_output}\n, interactor_error: {interactor_error}\n 'isolate': {}",
+            interactor_result.status_message.as_deref().unwrap_or("")
+        );
+        if let Some(failure) = standard::exceeded_limits(interactor_result.status) {
+            interactor_message = format!("interactor {failure}\n{interactor_message}");
         }
+        let (interactor_verdict, interactor_score) = match interactor_result.status {
+            RunStatus::Tl | RunStatus::Ml | RunStatus::Sg(_) => (test::Verdict::Te, 0.),
+            RunStatus::Ok => (test::Verdict::Ok, 1.),
+            RunStatus::Re(code) => {
+                standard::testlib_verdict(code, &interactor_error, &interactor_output)
+            }
+        };
 
-        let (verdict, message) = match interactor_result.status {
-            RunStatus::Ml | RunStatus::Sg(_) => (
-                test::Verdict::Te,
-                format!(
-                    "interactor_output: {interactor_output}\n, interactor_error: {interactor_error}\n 'isolate': {}",
-                    interactor_result.status_message.as_deref().unwrap_or("")
-                ),
-            ),
-            RunStatus::Ok => (
-                test::Verdict::Ok,
-                format!(
-                    "interactor_output: {interactor_output}\n, interactor_error: {interactor_error}\n 'isolate': {}",
-                    interactor_result.status_message.as_deref().unwrap_or("")
-                ),
-            ),
-            RunStatus::Tl => (
-                test::Verdict::Tl,
+        let attribution = attribute(interactor_verdict, failed_instance.is_some());
+        let (verdict, score, message, comment) = match (attribution, failed_instance) {
+            (Attribution::Instance, Some((i, verdict, status_message))) => (
+                verdict,
+                0.,
                 format!(
-                    "interactor_output: {interactor_output}\n, interactor_error: {interactor_error}\n 'isolate': {}",
-                    interactor_result.status_message.as_deref().unwrap_or("")
+                    "INSTANCE: {i}\nISOLATE: {status_message}\nINTERACTOR_ERRORS: {interactor_error}"
                 ),
+                None,
             ),
-            RunStatus::Re(code) => (
-                match code {
-                    1 => test::Verdict::Wa,
-                    2 => test::Verdict::Pe,
-                    _ => test::Verdict::Te,
-                },
-                format!(
-                    "interactor_output: {interactor_output}\n, interactor_error: {interactor_error}"
-                ),
+            (Attribution::Checker, _) if self.has_checker().await? => {
+                let (verdict, score, message, comment) = self.check_output().await?;
+                (
+                    verdict,
+                    score,
+                    format!("{interactor_message}\n{message}"),
+                    comment,
+                )
+            }
+            _ => (
+                interactor_verdict,
+                interactor_score,
+                interactor_message,
+                None,
             ),
         };
 
         let result = test::Result {
             verdict,
+            score,
             message: Arc::from(message),
+            comment,
+            transcript,
 
             output: interactor_output,
-            memory: solution_result.memory,
-            time: solution_result.time,
+            truncated,
+            memory,
+            time,
         };
 
         log::info!("({log_state}) judgement result:\n{result:#?}");
@@ -262,3 +529,46 @@ impl super::Enviroment for Enviroment {
         Ok(result)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn interactor_failure_and_wrong_answer_are_final() {
+        for instance_failed in [false, true] {
+            assert_eq!(
+                attribute(test::Verdict::Fl, instance_failed),
+                Attribution::Interactor
+            );
+            assert_eq!(
+                attribute(test::Verdict::Wa, instance_failed),
+                Attribution::Interactor
+            );
+        }
+    }
+
+    #[test]
+    fn failed_instance_wins_over_presentation_error() {
+        for interactor in [
+            test::Verdict::Pe,
+            test::Verdict::Pc,
+            test::Verdict::Ok,
+            test::Verdict::Te,
+        ] {
+            assert_eq!(attribute(interactor, true), Attribution::Instance);
+        }
+    }
+
+    #[test]
+    fn interactor_verdict_without_failed_instance() {
+        assert_eq!(attribute(test::Verdict::Pe, false), Attribution::Interactor);
+        assert_eq!(attribute(test::Verdict::Pc, false), Attribution::Interactor);
+        assert_eq!(attribute(test::Verdict::Te, false), Attribution::Interactor);
+    }
+
+    #[test]
+    fn accepted_interaction_is_checked() {
+        assert_eq!(attribute(test::Verdict::Ok, false), Attribution::Checker);
+    }
+}
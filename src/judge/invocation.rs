@@ -0,0 +1,124 @@
+use std::sync::Arc;
+
+use tar_archive_rs as archive;
+use tokio::{fs::File, io::AsyncRead};
+
+use super::{
+    Lang, Service,
+    api::{invocation, submission, test},
+    payload,
+};
+use crate::{
+    LogState, Result,
+    prelude::*,
+    sandbox::{MaybeLimited::*, RunStatus},
+};
+
+const STDIN_NAME: &str = "stdin";
+
+const TARGET_SOLUTION_PATH: &str = "solution.out";
+const TARGET_STDIN_PATH: &str = "stdin.txt";
+const TARGET_STDOUT_PATH: &str = "stdout.txt";
+const TARGET_STDERR_PATH: &str = "stderr.txt";
+
+impl Service {
+    /// Compiles solution and runs it once on custom input, without task package and checker.
+    /// `package` contains `solution` source and optional `stdin`,
+    /// set `limits` replace `invocation_limits` of judge config
+    pub async fn invoke<R: Unpin + AsyncRead>(
+        self: Arc<Self>,
+        submission: &str,
+        lang: Lang,
+        limits: submission::LimitsOverride,
+        mut package: archive::Archive<R>,
+    ) -> Result<invocation::Result> {
+        let (_permit, job) = self.start_job(submission).await?;
+        let work_dir = &*job.work_dir;
+        package.unpack(work_dir).await?;
+
+        let compilation = self
+            .compile_solution(work_dir, lang, None)
+            .await
+            .context("solution compiling")?;
+        match compilation.exit_code {
+            Some(0) => (),
+            Some(_) => return Ok(invocation::Result::Ce(compilation)),
+            None => return Ok(invocation::Result::Te(compilation.stderr)),
+        }
+
+        let sandbox = Arc::new(
+            Arc::clone(&self.sandboxes)
+                .initialize_sandbox()
+                .await
+                .context("sandbox initializing")?,
+        );
+        let log_state = LogState::new()
+            .push("submission", submission)
+            .push("box", &*format!("{}", sandbox.id()));
+
+        sandbox
+            .write_into_box(
+                &mut File::open(&*super::path_from(
+                    work_dir,
+                    super::SOLUTION_NAME,
+                    super::SOLUTION_EXT,
+                ))
+                .await?,
+                TARGET_SOLUTION_PATH,
+            )
+            .await?;
+        match File::open(format!("{work_dir}/{STDIN_NAME}")).await {
+            Ok(mut stdin) => {
+                sandbox
+                    .write_into_box(&mut stdin, TARGET_STDIN_PATH)
+                    .await?
+            }
+            Err(_) => {
+                log::debug!("({log_state}) stdin not found, empty one is used");
+                sandbox
+                    .write_into_box(&mut &b""[..], TARGET_STDIN_PATH)
+                    .await?
+            }
+        }
+
+        let limits = limits.apply(self.config.invocation_limits);
+        let mut cmd = lang.command_to_run(TARGET_SOLUTION_PATH);
+        cmd.time(Limited(limits.time))
+            .memory(Limited(limits.memory))
+            .real_time(Limited(limits.real_time));
+        if let Some(stack) = limits.stack {
+            cmd.stack(Limited(stack));
+        }
+        cmd.count_files(Limited(4))
+            .count_process(Limited(1))
+            .stdin(TARGET_STDIN_PATH)
+            .stdout(TARGET_STDOUT_PATH)
+            .stderr(TARGET_STDERR_PATH);
+
+        let result = sandbox.run(&cmd).await?;
+        log::info!("({log_state}) custom invocation: {:?}", result.status);
+
+        let read_output = async |path| match sandbox.read_from_box(path).await {
+            Ok(file) => payload::read_truncated(file, self.config.data.limit)
+                .await
+                .map(|(text, _)| text)
+                .unwrap_or_default(),
+            Err(_) => String::new(),
+        };
+        let stdout = read_output(TARGET_STDOUT_PATH).await.into_boxed_str();
+        let stderr = read_output(TARGET_STDERR_PATH).await.into_boxed_str();
+
+        Ok(invocation::Result::Ok(invocation::Report {
+            status: test::Verdict::from_run_status(result.status).unwrap_or(test::Verdict::Ok),
+            exit_code: match result.status {
+                RunStatus::Ok => Some(0),
+                RunStatus::Re(code) => Some(code),
+                RunStatus::Tl | RunStatus::Ml | RunStatus::Sg(_) => None,
+            },
+            time: result.time,
+            memory: result.memory,
+            stdout,
+            stderr,
+        }))
+    }
+}
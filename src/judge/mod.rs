@@ -1,7 +1,19 @@
 pub mod api;
-// mod double_run;
+mod builtin;
+mod cache;
+mod double_run;
+mod generator;
+mod hack;
 mod interactive;
+mod invocation;
+mod output_only;
+mod package;
+pub mod payload;
+mod problems;
+mod scheduler;
 mod standard;
+mod transcript;
+mod validator;
 
 use crate::prelude::*;
 
@@ -9,13 +21,21 @@ use async_trait::async_trait;
 use serde::{Deserialize, Serialize};
 use tar_archive_rs as archive;
 use tokio::{
-    fs::{File, create_dir, create_dir_all, remove_dir_all},
+    fs::{File, create_dir, create_dir_all},
     io::AsyncReadExt,
-    sync::{Mutex, Semaphore, mpsc::UnboundedSender},
+    sync::{Mutex, Semaphore, SemaphorePermit, mpsc::UnboundedSender},
     task::JoinHandle,
 };
 
-use std::{collections::HashMap, fs::Permissions, os::unix::fs::PermissionsExt, sync::Arc};
+use std::{
+    collections::HashMap,
+    fs::Permissions,
+    os::unix::fs::PermissionsExt,
+    sync::{
+        Arc,
+        atomic::{AtomicUsize, Ordering},
+    },
+};
 
 use crate::{
     LogState, Result,
@@ -24,6 +44,7 @@ use crate::{
 use configo::Config as _;
 
 use api::{
+    compilation,
     submission::{self, Task},
     test,
 };
@@ -37,6 +58,18 @@ pub enum Lang {
     Python,
 }
 
+impl std::str::FromStr for Lang {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "g++" => Ok(Self::Gpp),
+            "python3" => Ok(Self::Python),
+            _ => bail!("unknown lang '{s}'"),
+        }
+    }
+}
+
 impl Lang {
     pub fn command_to_run(&self, name: &str) -> Command {
         match self {
@@ -53,12 +86,72 @@ impl Lang {
 #[derive(Debug, Serialize, Deserialize)]
 struct Config {
     compilation_commands: HashMap<Lang, Box<[Box<str>]>>,
+    #[serde(default = "default_grader_compilation_commands")]
+    grader_compilation_commands: HashMap<Lang, Box<[Box<str>]>>,
+    #[serde(default = "default_submissions_limit")]
+    submissions_limit: usize,
+    #[serde(default = "default_problems_cache_size")]
+    problems_cache_size: u64, // bytes
+    #[serde(default = "default_tests_cache_size")]
+    tests_cache_size: u64, // bytes
+    #[serde(default)]
+    lang_limits: HashMap<Lang, submission::LangLimits>,
+    #[serde(default = "default_invocation_limits")]
+    invocation_limits: submission::Limits,
+    #[serde(default)]
+    data: payload::DataConfig,
+}
+
+fn commands_map(commands: Vec<(Lang, Vec<&str>)>) -> HashMap<Lang, Box<[Box<str>]>> {
+    commands
+        .into_iter()
+        .map(|(k, v)| (k, v.into_iter().map(|s| s.into()).collect()))
+        .collect()
+}
+
+fn default_grader_compilation_commands() -> HashMap<Lang, Box<[Box<str>]>> {
+    commands_map(vec![
+        (
+            Lang::Gpp,
+            vec![
+                "/usr/bin/g++",
+                "$SOURCE",
+                "-o",
+                "$OUTPUT",
+                "-O2",
+                "-Wall",
+                "-lm",
+            ],
+        ),
+        (Lang::Python, vec!["/bin/sh", "-c", "cat $SOURCE > $OUTPUT"]),
+    ])
+}
+
+fn default_submissions_limit() -> usize {
+    4
+}
+
+fn default_problems_cache_size() -> u64 {
+    4 << 30
+}
+
+fn default_tests_cache_size() -> u64 {
+    4 << 30
+}
+
+fn default_invocation_limits() -> submission::Limits {
+    submission::Limits {
+        time: 2.,
+        real_time: 5.,
+        memory: 262144,
+        stack: None,
+    }
 }
 
 impl Default for Config {
     fn default() -> Self {
         Self {
-            compilation_commands: vec![
+            compilation_commands: commands_map(vec![
                 (
                     Lang::Gpp,
                     vec![
@@ -69,17 +162,20 @@ impl Default for Config {
                         "-O2",
                         "-Wall",
                         "-lm",
-                    ]
-                    .into(),
+                    ],
                 ),
                 (
                     Lang::Python,
                     vec!["/usr/bin/cp", "--update=none", "$SOURCE", "$OUTPUT"],
                 ),
-            ]
-            .into_iter()
-            .map(|(k, v)| (k, v.into_iter().map(|s| s.into()).collect()).into())
-            .collect(),
+            ]),
+            grader_compilation_commands: default_grader_compilation_commands(),
+            submissions_limit: default_submissions_limit(),
+            problems_cache_size: default_problems_cache_size(),
+            tests_cache_size: default_tests_cache_size(),
+            lang_limits: HashMap::new(),
+            invocation_limits: default_invocation_limits(),
+            data: payload::DataConfig::default(),
         }
     }
 }
@@ -89,15 +185,36 @@ impl configo::Config for Config {
 }
 
 impl Config {
-    pub fn compilation_command(&self, lang: Lang, name: &str, result: &str) -> Result<Command> {
-        let mut args = self
-            .compilation_commands
+    /// Argument equal to `$SOURCE` is expanded into all `sources`,
+    /// otherwise `$SOURCE` is replaced by space separated `sources`
+    pub fn compilation_command(
+        &self,
+        lang: Lang,
+        with_grader: bool,
+        sources: &[&str],
+        result: &str,
+    ) -> Result<Command> {
+        let commands = if with_grader {
+            &self.grader_compilation_commands
+        } else {
+            &self.compilation_commands
+        };
+        let mut args = commands
             .get(&lang)
             .ok_or(anyhow!(
                 "cannot find compilation command for lang: {lang:?} in judge config"
             ))?
             .iter()
-            .map(|s| s.replace("$SOURCE", name).replace("$OUTPUT", result));
+            .flat_map(|s| {
+                if &**s == "$SOURCE" {
+                    sources.iter().map(|s| s.to_string()).collect()
+                } else {
+                    vec![
+                        s.replace("$SOURCE", &sources.join(" "))
+                            .replace("$OUTPUT", result),
+                    ]
+                }
+            });
         let mut command = Command::new(args.next().ok_or(anyhow!(
             "cannot find program name for lang: {lang:?} in judge config"
         ))?);
@@ -109,8 +226,12 @@ impl Config {
 pub struct Service {
     config: Config,
     work_dir: Box<str>,
+    cache_dir: Box<str>,
 
     semaphore: Semaphore,
+    next_job: AtomicUsize,
+    active_jobs: AtomicUsize,
+    problems_lock: Mutex<()>,
     sandboxes: Arc<sandbox::Service>,
     handler: Mutex<Option<JoinHandle<()>>>,
 }
@@ -118,6 +239,7 @@ pub struct Service {
 const CHANNEL_DIR: &str = "/.invoker";
 const SOLUTION_NAME: &str = "solution";
 const SOLUTION_EXT: Option<&str> = Some("out");
+const GRADER_DIR: &str = "grader";
 
 pub fn path_from(dir: &str, name: &str, ext: Option<&str>) -> Box<str> {
     format!(
@@ -127,6 +249,64 @@ pub fn path_from(dir: &str, name: &str, ext: Option<&str>) -> Box<str> {
     .into_boxed_str()
 }
 
+/// Directory and extension of test files given to solution (or interactor)
+pub fn tests_location(r#type: &submission::Type) -> (&'static str, Option<&'static str>) {
+    match r#type {
+        submission::Type::Interactive => (interactive::TEST_DIR, interactive::TEST_EXT),
+        _ => (standard::INPUT_DIR, standard::INPUT_EXT),
+    }
+}
+
+/// Reads `config.yaml` of unpacked package
+async fn read_task(work_dir: &str) -> Result<Task> {
+    let mut text = String::new();
+    File::open(&format!("{work_dir}/config.yaml"))
+        .await
+        .map_err(|_| {
+            OperatorError(Box::from(
+                "task package is malformed:\n'config.yaml' not found",
+            ))
+        })?
+        .read_to_string(&mut text)
+        .await?;
+
+    log::trace!("config.yaml:\n{text}");
+
+    Ok(serde_yml::from_str(text.as_str()).map_err(|e| {
+        OperatorError(format!("task package is malformed:\n'config.yaml': {e}").into())
+    })?)
+}
+
+/// Error caused by incorrect task package, reported to operator instead of verdict
+#[derive(Debug)]
+pub struct OperatorError(pub Box<str>);
+
+impl std::fmt::Display for OperatorError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl std::error::Error for OperatorError {}
+
+/// Running submission, its work directory is removed when judging ends or is cancelled
+struct Job {
+    service: Arc<Service>,
+    work_dir: Box<str>,
+}
+
+impl Drop for Job {
+    fn drop(&mut self) {
+        self.service.active_jobs.fetch_sub(1, Ordering::Relaxed);
+        let work_dir = std::mem::take(&mut self.work_dir);
+        tokio::spawn(async move {
+            if let Err(e) = tokio::fs::remove_dir_all(&*work_dir).await {
+                log::error!("work directory '{work_dir}' removing: {e}");
+            }
+        });
+    }
+}
+
 #[async_trait]
 pub trait Enviroment: Send {
     async fn run(self: Box<Self>) -> Result<test::Result>;
@@ -137,24 +317,38 @@ impl Service {
         config_dir: &str,
         sandboxes: Arc<sandbox::Service>,
         work_dir: Box<str>,
+        cache_dir: Box<str>,
     ) -> Service {
         if !tokio::fs::try_exists(&*work_dir).await.unwrap() {
             create_dir(&*work_dir).await.unwrap();
         }
+        if !tokio::fs::try_exists(&*cache_dir).await.unwrap() {
+            create_dir_all(&*cache_dir).await.unwrap();
+        }
         if !tokio::fs::try_exists(CHANNEL_DIR).await.unwrap() {
             create_dir_all(CHANNEL_DIR).await.unwrap();
         }
+        let config = Config::load(config_dir).await;
         Service {
-            config: Config::load(config_dir).await,
+            semaphore: Semaphore::new(config.submissions_limit),
+            next_job: AtomicUsize::new(0),
+            active_jobs: AtomicUsize::new(0),
+            problems_lock: Mutex::new(()),
+            config,
             work_dir,
+            cache_dir,
             sandboxes,
             handler: Mutex::new(None),
-            semaphore: Semaphore::new(1),
         }
     }
 
+    pub fn data_config(&self) -> payload::DataConfig {
+        self.config.data
+    }
+
+    /// Cleans sandboxes after running jobs are aborted by their handles,
+    /// the semaphore stays open, so later jobs are started as usual
     pub async fn cancel_all_tests(&self) -> Result<()> {
-        self.semaphore.close();
         if let Some(handler) = &*self.handler.lock().await {
             handler.abort();
         }
@@ -163,171 +357,244 @@ impl Service {
         Ok(())
     }
 
-    async fn compile_solution(&self, lang: Lang) -> Result<Option<submission::Result>> {
-        let sandbox = Arc::clone(&self.sandboxes)
-            .initialize_sandbox()
-            .await
-            .context("sandbox initializing")?;
+    async fn compile_solution(
+        &self,
+        work_dir: &str,
+        lang: Lang,
+        grader: Option<&submission::Grader>,
+    ) -> Result<compilation::Report> {
+        self.compile(
+            work_dir,
+            lang,
+            &path_from(work_dir, SOLUTION_NAME, None),
+            grader,
+            &path_from(work_dir, SOLUTION_NAME, SOLUTION_EXT),
+        )
+        .await
+    }
+
+    /// Compiles `src_path` into `dst_path`, which is created only if compilation succeeded,
+    /// grader files are taken from `work_dir`
+    async fn compile(
+        &self,
+        work_dir: &str,
+        lang: Lang,
+        src_path: &str,
+        grader: Option<&submission::Grader>,
+        dst_path: &str,
+    ) -> Result<compilation::Report> {
+        let sandbox = Arc::new(
+            Arc::clone(&self.sandboxes)
+                .initialize_sandbox()
+                .await
+                .context("sandbox initializing")?,
+        );
 
         let mut log_state = LogState::new();
         log_state = log_state.push("box", &*format!("{}", sandbox.id()));
 
         sandbox
-            .write_into_box(
-                &mut File::open(format!("{}/solution", &*self.work_dir)).await?,
-                "solution.cpp",
-            )
+            .write_into_box(&mut File::open(src_path).await?, "solution.cpp")
             .await?;
 
+        let mut sources = vec!["solution.cpp"];
+        if let Some(grader) = grader {
+            // written after the solution, so grader files always win
+            let mut files = vec![];
+            for name in grader.sources.iter().chain(grader.headers.iter()) {
+                files.push((
+                    File::open(format!("{work_dir}/{GRADER_DIR}/{name}"))
+                        .await
+                        .context(format!("opening grader file '{name}'"))?,
+                    name.clone(),
+                ));
+            }
+            Arc::clone(&sandbox)
+                .write_group_into_box(files.into_boxed_slice())
+                .await?;
+            sources.extend(grader.sources.iter().map(|s| &**s));
+        }
+
+        let compile_output_path = "compile_output";
         let compile_errors_path = "compile_errors";
         let mut compilation_command =
             self.config
-                .compilation_command(lang, "solution.cpp", "solution.out")?;
+                .compilation_command(lang, grader.is_some(), &sources, "solution.out")?;
         compilation_command
             .count_files(MaybeLimited::Unlimited)
             .count_process(MaybeLimited::Unlimited)
             .use_env()
+            .stdout(compile_output_path)
             .stderr(compile_errors_path);
 
         let compile_result = sandbox.run(&compilation_command).await?;
 
         log::info!("({log_state}) compiling");
 
-        match compile_result.status {
-            sandbox::RunStatus::Tl | sandbox::RunStatus::Ml | sandbox::RunStatus::Sg(_) => {
-                let mut message = String::new();
-                if let Ok(mut r) = sandbox.read_from_box(compile_errors_path).await {
-                    r.read_to_string(&mut message).await?;
-                }
-                return Ok(Some(submission::Result::Te(message.into_boxed_str())));
-            }
-            sandbox::RunStatus::Re(_) => {
-                let mut message = String::new();
-                if let Ok(mut r) = sandbox.read_from_box(compile_errors_path).await {
-                    r.read_to_string(&mut message).await?;
-                }
-
-                return Ok(Some(submission::Result::Ce(message.into_boxed_str())));
-            }
-            _ => (),
+        // compiler messages are sent in `VERDICT`, so they are truncated as test data
+        let read_output = async |path| match sandbox.read_from_box(path).await {
+            Ok(file) => payload::read_truncated(file, self.config.data.limit)
+                .await
+                .map(|(text, _)| text)
+                .unwrap_or_default(),
+            Err(_) => String::new(),
+        };
+        let report = compilation::Report {
+            exit_code: match compile_result.status {
+                sandbox::RunStatus::Ok => Some(0),
+                sandbox::RunStatus::Re(code) => Some(code),
+                sandbox::RunStatus::Tl | sandbox::RunStatus::Ml | sandbox::RunStatus::Sg(_) => None,
+            },
+            time: compile_result.time,
+            memory: compile_result.memory,
+            stdout: read_output(compile_output_path).await.into_boxed_str(),
+            stderr: read_output(compile_errors_path).await.into_boxed_str(),
         };
 
-        let mut file = tokio::fs::File::create(format!("{}/solution.out", self.work_dir)).await?;
-        tokio::io::copy(&mut sandbox.read_from_box("solution.out").await?, &mut file).await?;
-        file.set_permissions(Permissions::from_mode(0o777)).await?;
-        Ok(None)
+        if report.exit_code == Some(0) {
+            let mut file = tokio::fs::File::create(dst_path).await?;
+            tokio::io::copy(&mut sandbox.read_from_box("solution.out").await?, &mut file).await?;
+            file.set_permissions(Permissions::from_mode(0o777)).await?;
+        }
+        Ok(report)
     }
 
+    async fn unpack_outputs(&self, work_dir: &str) -> Result<()> {
+        let outputs_dir = format!("{work_dir}/{}", output_only::OUTPUT_DIR);
+        create_dir_all(&outputs_dir).await?;
+        archive::Archive::new(File::open(format!("{work_dir}/solution")).await?)
+            .unpack(&outputs_dir)
+            .await?;
+        Ok(())
+    }
+
+    /// Judges submission as a separate job with its own work directory,
+    /// waits while there are `submissions_limit` jobs running.
+    /// `solution` replaces solution and its lang from `package`
     pub async fn judge<R: Unpin + tokio::io::AsyncRead>(
         self: Arc<Self>,
-        mut package: archive::Archive<R>,
+        submission: &str,
+        package: archive::Archive<R>,
+        solution: Option<(Lang, Box<[u8]>)>,
         sender: UnboundedSender<(usize, test::Result)>,
     ) -> Result<submission::Result> {
-        let permit = self.semaphore.try_acquire()?;
-        log::info!("testing started");
-        package.unpack(&*self.work_dir).await?;
-
-        let mut text = String::new();
-        File::open(&format!("{}/config.yaml", &self.work_dir))
-            .await?
-            .read_to_string(&mut text)
-            .await?;
+        let (_permit, job) = self.start_job(submission).await?;
+        self.judge_job(&job.work_dir, package, solution, sender)
+            .await
+    }
 
-        log::trace!("config.yaml:\n{text}");
+    /// Waits for a free slot and creates a job for `submission`
+    async fn start_job(self: &Arc<Self>, submission: &str) -> Result<(SemaphorePermit<'_>, Job)> {
+        let permit = self.semaphore.acquire().await?;
+        let id = self.next_job.fetch_add(1, Ordering::Relaxed);
+        self.active_jobs.fetch_add(1, Ordering::Relaxed);
+        let job = Job {
+            service: Arc::clone(self),
+            work_dir: format!("{}/{id}", self.work_dir).into_boxed_str(),
+        };
+        create_dir_all(&*job.work_dir).await?;
+        let log_state = LogState::new()
+            .push("submission", submission)
+            .push("job", &*format!("{id}"));
+        log::info!("({log_state}) job started");
+        Ok((permit, job))
+    }
 
-        let task: Arc<Task> = Arc::new(serde_yml::from_str(text.as_str())?);
+    async fn judge_job<R: Unpin + tokio::io::AsyncRead>(
+        &self,
+        work_dir: &str,
+        mut package: archive::Archive<R>,
+        solution: Option<(Lang, Box<[u8]>)>,
+        sender: UnboundedSender<(usize, test::Result)>,
+    ) -> Result<submission::Result> {
+        package.unpack(work_dir).await?;
+        let lang = match solution {
+            Some((lang, source)) => {
+                tokio::fs::write(&*path_from(work_dir, SOLUTION_NAME, None), source).await?;
+                Some(lang)
+            }
+            None => None,
+        };
+        let mut task = read_task(work_dir).await?;
+        if let Some(lang) = lang {
+            task.lang = lang;
+        }
+        let task = Arc::new(task);
         let lang = task.lang;
 
-        if let Some(verdict) = self
-            .compile_solution(lang)
-            .await
-            .context("solution compiling")?
-        {
-            return Ok(verdict);
+        if let Some(generation) = &task.generation {
+            self.generate_tests(work_dir, &task, generation)
+                .await
+                .context("tests generating")?;
         }
 
-        let mut handlers: Vec<JoinHandle<Result<()>>> = vec![];
-
-        let blocked_groups = Arc::new(Mutex::new(vec![None; task.groups.len()].into_boxed_slice()));
-
-        for group in task.groups.clone() {
-            'test: for test_number in (group.range.0 - 1)..group.range.1 {
-                let mut log_state = LogState::new();
-                log_state = log_state.push("test", &*format!("{test_number}"));
-                log::trace!("({log_state}) looking on test");
-
-                if blocked_groups.lock().await[group.id].is_some() {
-                    continue;
-                }
-                for depend in &group.depends {
-                    if blocked_groups.lock().await[*depend].is_some() {
-                        continue 'test;
-                    }
-                }
+        package::check(work_dir, &task)
+            .await
+            .context("task package checking")?;
 
-                log::trace!("({log_state}) test started");
+        validator::validate(Arc::clone(&self.sandboxes), work_dir, &task)
+            .await
+            .context("tests validating")?;
 
-                let task = Arc::clone(&task);
-                let enviroment = self
-                    .prepare(task, test_number, log_state)
+        let compilation = match task.r#type {
+            submission::Type::OutputOnly => {
+                self.unpack_outputs(work_dir)
                     .await
-                    .context("enviroment preparing")?;
-
-                let blocked_groups = Arc::clone(&blocked_groups);
-                let sender = sender.clone();
-
-                handlers.push(tokio::spawn(async move {
-                    let result = enviroment.run().await.context("enviroment running")?;
-                    sender.send((test_number + 1, result.clone())).unwrap();
-                    if !result.verdict.is_success() {
-                        let block = &mut blocked_groups.lock().await[group.id];
-                        if let Some(id) = block {
-                            *block = Some(std::cmp::min(*id, test_number));
-                        } else {
-                            *block = Some(test_number);
-                        }
-                    }
-                    Ok(())
-                }));
+                    .context("outputs unpacking")?;
+                None
             }
-        }
-
-        log::trace!("waiting all test processes");
+            _ => {
+                let compilation = self
+                    .compile_solution(work_dir, lang, task.grader.as_ref())
+                    .await
+                    .context("solution compiling")?;
+                match compilation.exit_code {
+                    Some(0) => (),
+                    Some(_) => return Ok(submission::Result::Ce(compilation)),
+                    None => return Ok(submission::Result::Te(compilation.stderr)),
+                }
+                Some(compilation)
+            }
+        };
 
-        for handler in handlers {
-            handler.await??;
+        if let submission::Type::Interactive = task.r#type {
+            self.compile_instances(work_dir, &task)
+                .await
+                .context("instances compiling")?;
         }
-        let blocked_groups = blocked_groups.lock().await;
 
-        let groups_score: Box<[usize]> = (0..task.groups.len())
-            .into_iter()
-            .map(|i| {
-                if blocked_groups[i].is_none() {
-                    task.groups[i].cost
-                } else {
-                    0
-                }
-            })
-            .collect();
+        let (groups_score, summary) = self
+            .run_tests(work_dir, Arc::clone(&task), sender)
+            .await
+            .context("tests running")?;
 
         let result = submission::Result::Ok {
             score: groups_score.iter().sum(),
             groups_score,
+            limits: self.lang_limits(&task, task.limits),
+            compilation,
+            summary,
         };
 
         log::info!("full result: {result:?}");
-
-        remove_dir_all(&*self.work_dir)
-            .await
-            .context("dirs cleaning")?;
-        tokio::fs::create_dir(&*self.work_dir).await?;
-        drop(permit);
         Ok(result)
     }
 
+    /// Scales `limits` for task solution lang, scales from task take precedence over judge config
+    fn lang_limits(&self, task: &Task, limits: submission::Limits) -> submission::Limits {
+        task.lang_limits
+            .get(&task.lang)
+            .or(self.config.lang_limits.get(&task.lang))
+            .copied()
+            .unwrap_or_default()
+            .apply(limits)
+    }
+
     async fn prepare(
         &self,
+        work_dir: &str,
         task: Arc<Task>,
+        limits: submission::Limits,
         test_id: usize,
         log_state: Arc<LogState>,
     ) -> Result<Box<dyn Enviroment>> {
@@ -336,8 +603,11 @@ impl Service {
                 standard::prepare(
                     Arc::clone(&self.sandboxes),
                     task.lang,
-                    task.limits,
-                    self.work_dir.clone(),
+                    limits,
+                    task.checker,
+                    task.checker_limits,
+                    self.config.data.limit,
+                    Box::from(work_dir),
                     test_id,
                     log_state,
                 )
@@ -348,14 +618,48 @@ impl Service {
                 interactive::prepare(
                     Arc::clone(&self.sandboxes),
                     task.lang,
-                    task.limits,
-                    self.work_dir.clone(),
+                    limits,
+                    task.checker,
+                    task.checker_limits,
+                    task.interactor_limits,
+                    task.transcript,
+                    task.instances.clone(),
+                    self.config.data.limit,
+                    Box::from(work_dir),
                     test_id,
                     log_state,
                 )
                 .await
                 .context("interactive preparing")?,
             ) as Box<dyn Enviroment>,
+            submission::Type::DoubleRun => Box::from(
+                double_run::prepare(
+                    Arc::clone(&self.sandboxes),
+                    task.lang,
+                    limits,
+                    task.checker,
+                    task.checker_limits,
+                    self.config.data.limit,
+                    Box::from(work_dir),
+                    test_id,
+                    log_state,
+                )
+                .await
+                .context("double run preparing")?,
+            ) as Box<dyn Enviroment>,
+            submission::Type::OutputOnly => Box::from(
+                output_only::prepare(
+                    Arc::clone(&self.sandboxes),
+                    task.checker,
+                    task.checker_limits,
+                    self.config.data.limit,
+                    Box::from(work_dir),
+                    test_id,
+                    log_state,
+                )
+                .await
+                .context("output only preparing")?,
+            ) as Box<dyn Enviroment>,
         })
     }
 }
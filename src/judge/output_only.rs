@@ -0,0 +1,150 @@
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use tokio::fs::File;
+
+use super::{
+    api::{submission, test},
+    path_from, payload,
+    standard::{
+        CHECKER_EXT, CHECKER_NAME, CORRECT_DIR, CORRECT_EXT, INPUT_DIR, INPUT_EXT,
+        TARGET_CHECKER_PATH, TARGET_CORRECT_PATH, TARGET_INPUT_PATH, TARGET_OUTPUT_PATH, check,
+    },
+};
+use crate::{LogState, Result, sandbox};
+
+pub(super) const OUTPUT_DIR: &str = "output";
+const OUTPUT_EXT: Option<&str> = Some("txt");
+
+pub struct Enviroment {
+    sandbox: Arc<sandbox::Sandbox>,
+    checker: submission::Checker,
+    checker_limits: submission::LimitsOverride,
+
+    data_limit: usize,
+    work_dir: Box<str>,
+    test_id: usize,
+    log_state: Arc<LogState>,
+}
+
+pub async fn prepare(
+    sandboxes: Arc<sandbox::Service>,
+    checker: submission::Checker,
+    checker_limits: submission::LimitsOverride,
+    data_limit: usize,
+    work_dir: Box<str>,
+
+    test_id: usize,
+    log_state: Arc<LogState>,
+) -> Result<Enviroment> {
+    let sandbox = Arc::new(sandboxes.initialize_sandbox().await?);
+
+    let log_state = log_state.push("box_id", &*format!("{}", sandbox.id()));
+
+    Ok(Enviroment {
+        sandbox,
+        checker,
+        checker_limits,
+        data_limit,
+        work_dir,
+        test_id,
+        log_state,
+    })
+}
+
+#[async_trait]
+impl super::Enviroment for Enviroment {
+    async fn run(self: Box<Self>) -> Result<test::Result> {
+        let log_state = self.log_state.push("task type", "OUTPUT_ONLY");
+        log::trace!("({log_state}) testing STARTED");
+        let src_input_path = path_from(
+            &format!("{}/{INPUT_DIR}", self.work_dir),
+            &format!("{}", self.test_id + 1),
+            INPUT_EXT,
+        );
+        let src_output_path = path_from(
+            &format!("{}/{OUTPUT_DIR}", self.work_dir),
+            &format!("{}", self.test_id + 1),
+            OUTPUT_EXT,
+        );
+        let src_correct_path = path_from(
+            &format!("{}/{CORRECT_DIR}", self.work_dir),
+            &format!("{}", self.test_id + 1),
+            CORRECT_EXT,
+        );
+        let src_checker_path = path_from(&self.work_dir, CHECKER_NAME, CHECKER_EXT);
+
+        let Ok(mut output_file) = File::open(&*src_output_path).await else {
+            log::debug!("({log_state}) output file not founded");
+            return Ok(test::Result {
+                verdict: test::Verdict::Wa,
+                score: 0.,
+                time: 0.,
+                memory: 0,
+                output: Arc::from(""),
+                truncated: false,
+                message: Arc::from(
+                    format!("output file '{}.txt' not found", self.test_id + 1).as_str(),
+                ),
+                comment: None,
+                transcript: None,
+            });
+        };
+
+        self.sandbox
+            .write_into_box(&mut output_file, TARGET_OUTPUT_PATH)
+            .await?;
+        let mut files = vec![(File::open(&*src_input_path).await?, TARGET_INPUT_PATH)];
+        if self.checker == submission::Checker::Package {
+            files.push((File::open(&*src_checker_path).await?, TARGET_CHECKER_PATH));
+        }
+        Arc::clone(&self.sandbox)
+            .write_group_into_box(
+                files
+                    .into_iter()
+                    .map(|(from, to)| (from, Box::from(to)))
+                    .collect(),
+            )
+            .await?;
+
+        if let Ok(mut correct) = File::open(&*src_correct_path).await {
+            self.sandbox
+                .write_into_box(&mut correct, TARGET_CORRECT_PATH)
+                .await?;
+        } else {
+            log::debug!("({log_state}) correct file not founded");
+        }
+
+        let (output, truncated) = payload::read_truncated(
+            self.sandbox.read_from_box(TARGET_OUTPUT_PATH).await?,
+            self.data_limit,
+        )
+        .await?;
+        let output: Arc<str> = Arc::from(output);
+
+        let (verdict, score, message, comment) = check(
+            Arc::clone(&self.sandbox),
+            self.checker,
+            self.checker_limits,
+            &log_state,
+        )
+        .await?;
+
+        let result = test::Result {
+            verdict,
+            score,
+            message: Arc::from(message),
+            comment,
+            transcript: None,
+
+            output,
+            truncated,
+            memory: 0,
+            time: 0.,
+        };
+
+        log::info!("({log_state}) judgement result:\n{result:#?}");
+
+        Ok(result)
+    }
+}
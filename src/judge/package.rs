@@ -0,0 +1,338 @@
+use super::{
+    GRADER_DIR, OperatorError,
+    api::submission::{self, Task},
+    interactive::{INTERACTOR_EXT, INTERACTOR_NAME},
+    path_from,
+    standard::{CHECKER_EXT, CHECKER_NAME, CORRECT_DIR, CORRECT_EXT},
+    tests_location,
+};
+use crate::Result;
+
+const TOTAL_COST: f64 = 100.;
+const COST_EPS: f64 = 1e-6;
+
+/// Checks `task` against itself and unpacked package files.
+/// Fails with `OperatorError` listing all found problems
+pub async fn check(work_dir: &str, task: &Task) -> Result<()> {
+    let mut problems = check_groups(task);
+    problems.extend(check_files(work_dir, task).await?);
+
+    if !problems.is_empty() {
+        return Err(OperatorError(
+            format!("task package is malformed:\n{}", problems.join("\n")).into_boxed_str(),
+        )
+        .into());
+    }
+    log::info!("task package is correct");
+    Ok(())
+}
+
+fn check_groups(task: &Task) -> Vec<String> {
+    let mut problems = vec![];
+    let count = task.groups.len();
+
+    if count == 0 {
+        problems.push("no groups".to_string());
+    }
+    for (i, group) in task.groups.iter().enumerate() {
+        if group.id != i {
+            problems.push(format!(
+                "group {i}: has id {}, groups must be listed by id",
+                group.id
+            ));
+        }
+        let (first, last) = (group.range.0, group.range.1);
+        if first == 0 || first > last {
+            problems.push(format!(
+                "group {i}: incorrect range [{first}, {last}], tests are numbered from 1"
+            ));
+        }
+        if !(0. ..=TOTAL_COST).contains(&group.cost) {
+            problems.push(format!(
+                "group {i}: cost {} is out of [0, {TOTAL_COST}]",
+                group.cost
+            ));
+        }
+        for depend in &group.depends {
+            if *depend >= count {
+                problems.push(format!("group {i}: depends on missing group {depend}"));
+            } else if *depend == i {
+                problems.push(format!("group {i}: depends on itself"));
+            }
+        }
+    }
+
+    let mut ranges: Vec<(usize, usize, usize)> = task
+        .groups
+        .iter()
+        .enumerate()
+        .filter(|(_, group)| group.range.0 != 0 && group.range.0 <= group.range.1)
+        .map(|(i, group)| (group.range.0, group.range.1, i))
+        .collect();
+    ranges.sort();
+    for pair in ranges.windows(2) {
+        let ((_, last, a), (first, _, b)) = (pair[0], pair[1]);
+        if first <= last {
+            problems.push(format!("groups {a} and {b}: ranges overlap"));
+        }
+    }
+    let last = ranges.iter().map(|(_, last, _)| *last).max().unwrap_or(0);
+    if let Some(missing) = (1..=last).find(|test| {
+        !ranges
+            .iter()
+            .any(|(first, last, _)| (first..=last).contains(&test))
+    }) {
+        problems.push(format!("test {missing}: not in any group"));
+    }
+    let mut limited: Vec<_> = task.test_limits.keys().copied().collect();
+    limited.sort();
+    for test_id in limited {
+        if !ranges
+            .iter()
+            .any(|(first, last, _)| (first..=last).contains(&&test_id))
+        {
+            problems.push(format!("test {test_id}: has limits, but not in any group"));
+        }
+    }
+
+    let total: f64 = task.groups.iter().map(|group| group.cost).sum();
+    if (total - TOTAL_COST).abs() > COST_EPS {
+        problems.push(format!("costs total {total}, expected {TOTAL_COST}"));
+    }
+
+    if let Some(cycle) = find_cycle(task) {
+        problems.push(format!(
+            "groups dependencies are cyclic: {}",
+            cycle
+                .iter()
+                .map(|id| id.to_string())
+                .collect::<Vec<_>>()
+                .join(" -> ")
+        ));
+    }
+    problems
+}
+
+/// Returns groups of some dependencies cycle, first group is repeated at the end
+fn find_cycle(task: &Task) -> Option<Vec<usize>> {
+    #[derive(Clone, Copy, PartialEq, Eq)]
+    enum Mark {
+        New,
+        InPath,
+        Done,
+    }
+
+    fn visit(task: &Task, id: usize, marks: &mut [Mark], path: &mut Vec<usize>) -> bool {
+        marks[id] = Mark::InPath;
+        path.push(id);
+        for &depend in &task.groups[id].depends {
+            if depend >= marks.len() || depend == id {
+                continue;
+            }
+            let mark = marks[depend];
+            match mark {
+                Mark::InPath => {
+                    let start = path.iter().position(|group| *group == depend).unwrap();
+                    path.drain(..start);
+                    path.push(depend);
+                    return true;
+                }
+                Mark::New if visit(task, depend, marks, path) => return true,
+                _ => (),
+            }
+        }
+        marks[id] = Mark::Done;
+        path.pop();
+        false
+    }
+
+    let mut marks = vec![Mark::New; task.groups.len()];
+    for id in 0..task.groups.len() {
+        let mut path = vec![];
+        if marks[id] == Mark::New && visit(task, id, &mut marks, &mut path) {
+            return Some(path);
+        }
+    }
+    None
+}
+
+async fn check_files(work_dir: &str, task: &Task) -> Result<Vec<String>> {
+    let mut problems = vec![];
+
+    let program = match (&task.r#type, task.checker) {
+        (submission::Type::Interactive, _) => Some((INTERACTOR_NAME, INTERACTOR_EXT)),
+        (_, submission::Checker::Package) => Some((CHECKER_NAME, CHECKER_EXT)),
+        (_, submission::Checker::Builtin(_)) => None,
+    };
+    if let Some((name, ext)) = program {
+        let path = path_from(work_dir, name, ext);
+        if !tokio::fs::try_exists(&*path).await? {
+            problems.push(format!("'{}' not found", &path[work_dir.len() + 1..]));
+        }
+    }
+
+    if let Some(grader) = &task.grader {
+        for name in grader.sources.iter().chain(&grader.headers) {
+            if !tokio::fs::try_exists(format!("{work_dir}/{GRADER_DIR}/{name}")).await? {
+                problems.push(format!("grader file '{GRADER_DIR}/{name}' not found"));
+            }
+        }
+    }
+
+    if task.instances.is_empty() {
+        problems.push("no instances".to_string());
+    }
+    for (i, instance) in task.instances.iter().enumerate() {
+        if let Some(program) = &instance.program
+            && !tokio::fs::try_exists(format!("{work_dir}/{program}")).await?
+        {
+            problems.push(format!("instance {i}: program '{program}' not found"));
+        }
+    }
+
+    let (tests_dir, tests_ext) = tests_location(&task.r#type);
+    for group in &task.groups {
+        if group.range.0 == 0 {
+            continue;
+        }
+        for test_id in group.range.0..=group.range.1 {
+            let path = path_from(
+                &format!("{work_dir}/{tests_dir}"),
+                &format!("{test_id}"),
+                tests_ext,
+            );
+            if !tokio::fs::try_exists(&*path).await? {
+                problems.push(format!(
+                    "test {test_id}: '{}' not found",
+                    &path[work_dir.len() + 1..]
+                ));
+            }
+            // builtin checkers compare with correct answer, generated ones already exist here
+            if let submission::Checker::Builtin(_) = task.checker {
+                let path = path_from(
+                    &format!("{work_dir}/{CORRECT_DIR}"),
+                    &format!("{test_id}"),
+                    CORRECT_EXT,
+                );
+                if !tokio::fs::try_exists(&*path).await? {
+                    problems.push(format!(
+                        "test {test_id}: '{}' not found, it is required by builtin checker",
+                        &path[work_dir.len() + 1..]
+                    ));
+                }
+            }
+        }
+    }
+    Ok(problems)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Standard task with groups of `(range, cost, depends)`
+    fn task(groups: &[((usize, usize), f64, &[usize])], extra: &str) -> Task {
+        let groups: Vec<String> = groups
+            .iter()
+            .enumerate()
+            .map(|(id, ((first, last), cost, depends))| {
+                format!(
+                    "- {{id: {id}, range: [{first}, {last}], cost: {cost}, depends: {depends:?}, scoring: all_or_nothing}}"
+                )
+            })
+            .collect();
+        let task = format!(
+            "type: standard\nlang: g++\nlimits: {{time: 1, real_time: 1, memory: 1024}}\ngroups:\n{}\n{extra}",
+            groups.join("\n")
+        );
+        serde_yml::from_str(&task).unwrap()
+    }
+
+    #[test]
+    fn correct_groups_have_no_problems() {
+        let task = task(&[((1, 2), 40., &[]), ((3, 5), 60., &[0])], "");
+        assert!(check_groups(&task).is_empty());
+    }
+
+    #[test]
+    fn overlapping_ranges_are_reported() {
+        let task = task(&[((1, 3), 40., &[]), ((3, 5), 60., &[])], "");
+        assert_eq!(check_groups(&task), ["groups 0 and 1: ranges overlap"]);
+    }
+
+    #[test]
+    fn tests_out_of_groups_are_reported() {
+        let task = task(
+            &[((1, 2), 40., &[]), ((4, 5), 60., &[]), ((0, 1), 0., &[])],
+            "test_limits: {7: {time: 2}}",
+        );
+        assert_eq!(
+            check_groups(&task),
+            [
+                "group 2: incorrect range [0, 1], tests are numbered from 1",
+                "test 3: not in any group",
+                "test 7: has limits, but not in any group",
+            ]
+        );
+    }
+
+    #[test]
+    fn missing_depends_are_reported() {
+        let task = task(&[((1, 2), 40., &[0]), ((3, 5), 60., &[2])], "");
+        assert_eq!(
+            check_groups(&task),
+            [
+                "group 0: depends on itself",
+                "group 1: depends on missing group 2"
+            ]
+        );
+    }
+
+    #[test]
+    fn cyclic_depends_are_reported() {
+        let task = task(
+            &[
+                ((1, 1), 10., &[]),
+                ((2, 2), 40., &[2]),
+                ((3, 3), 50., &[0, 1]),
+            ],
+            "",
+        );
+        assert_eq!(find_cycle(&task), Some(vec![1, 2, 1]));
+        assert_eq!(
+            check_groups(&task),
+            ["groups dependencies are cyclic: 1 -> 2 -> 1"]
+        );
+    }
+
+    #[test]
+    fn wrong_costs_total_is_reported() {
+        let task = task(&[((1, 2), 40., &[]), ((3, 5), 50., &[])], "");
+        assert_eq!(check_groups(&task), ["costs total 90, expected 100"]);
+    }
+
+    #[tokio::test]
+    async fn missing_correct_answer_is_reported_for_builtin_checker() {
+        let work_dir =
+            std::env::temp_dir().join(format!("package-check-{}", rand::random::<u64>()));
+        let work_dir = work_dir.to_str().unwrap();
+        for dir in ["input", "correct"] {
+            tokio::fs::create_dir_all(format!("{work_dir}/{dir}"))
+                .await
+                .unwrap();
+        }
+        for path in ["input/1.txt", "input/2.txt", "correct/1.txt"] {
+            tokio::fs::write(format!("{work_dir}/{path}"), "")
+                .await
+                .unwrap();
+        }
+
+        let task = task(&[((1, 2), 100., &[])], "checker: builtin:exact");
+        let problems = check_files(work_dir, &task).await.unwrap();
+        tokio::fs::remove_dir_all(work_dir).await.unwrap();
+        assert_eq!(
+            problems,
+            ["test 2: 'correct/2.txt' not found, it is required by builtin checker"]
+        );
+    }
+}
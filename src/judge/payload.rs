@@ -0,0 +1,269 @@
+use std::io::{SeekFrom, Write as _};
+
+use serde::{Deserialize, Serialize};
+use tokio::{
+    fs::File,
+    io::{AsyncReadExt as _, AsyncSeekExt as _},
+};
+
+use crate::Result;
+
+/// Limits and encoding of `DATA` of test verdicts
+#[derive(Debug, Serialize, Deserialize, Clone, Copy)]
+pub struct DataConfig {
+    #[serde(default = "default_limit")]
+    pub limit: usize, // bytes of every item, head and tail are kept
+    #[serde(default = "default_submission_limit")]
+    pub submission_limit: usize, // bytes of all test verdicts of submission
+    #[serde(default)]
+    pub encoding: Encoding,
+}
+
+fn default_limit() -> usize {
+    1 << 16
+}
+
+fn default_submission_limit() -> usize {
+    16 << 20
+}
+
+impl Default for DataConfig {
+    fn default() -> Self {
+        Self {
+            limit: default_limit(),
+            submission_limit: default_submission_limit(),
+            encoding: Encoding::default(),
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Encoding {
+    #[default]
+    None,
+    Zstd,
+    Gzip,
+}
+
+impl std::fmt::Display for Encoding {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}",
+            match self {
+                Self::None => "NONE",
+                Self::Zstd => "ZSTD",
+                Self::Gzip => "GZIP",
+            }
+        )
+    }
+}
+
+impl Encoding {
+    pub fn encode(&self, data: Box<[u8]>) -> Result<Box<[u8]>> {
+        Ok(match self {
+            Self::None => data,
+            Self::Zstd => zstd::encode_all(&*data, 0)?.into_boxed_slice(),
+            Self::Gzip => {
+                let mut encoder =
+                    flate2::write::GzEncoder::new(vec![], flate2::Compression::default());
+                encoder.write_all(&data)?;
+                encoder.finish()?.into_boxed_slice()
+            }
+        })
+    }
+}
+
+/// What was done with `DATA` to fit the limits
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DataState {
+    Full,
+    Truncated, // some items are cut to `limit`
+    Omitted,   // submission limit is exhausted, data is empty
+}
+
+impl std::fmt::Display for DataState {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}",
+            match self {
+                Self::Full => "FULL",
+                Self::Truncated => "TRUNCATED",
+                Self::Omitted => "OMITTED",
+            }
+        )
+    }
+}
+
+fn marker(skipped: u64) -> String {
+    format!("\n... {skipped} bytes truncated ...\n")
+}
+
+/// Bytes of text of `len` bytes kept around the marker, so that the result fits `limit`
+fn kept(len: u64, limit: usize) -> usize {
+    // skipped bytes never exceed `len`, so the marker is never longer
+    limit.saturating_sub(marker(len).len())
+}
+
+/// Decoded chars of `bytes` with lengths of their original bytes,
+/// invalid sequences (as a char split by cutting) become `U+FFFD`
+fn decode(bytes: &[u8]) -> Vec<(char, usize)> {
+    let mut chars = vec![];
+    for chunk in bytes.utf8_chunks() {
+        chars.extend(chunk.valid().chars().map(|c| (c, c.len_utf8())));
+        if !chunk.invalid().is_empty() {
+            chars.push((char::REPLACEMENT_CHARACTER, chunk.invalid().len()));
+        }
+    }
+    chars
+}
+
+/// Joins decoded `head` and `tail` of text of `len` bytes by the marker.
+/// Decoded head and tail never grow over `head.len()` and `tail.len()`,
+/// chars which do not fit are counted as skipped
+fn join(head: &[u8], len: u64, tail: &[u8]) -> String {
+    let (mut head_text, mut head_used, mut head_len) = (String::new(), 0, 0);
+    for (c, used) in decode(head) {
+        if head_len + c.len_utf8() > head.len() {
+            break;
+        }
+        head_text.push(c);
+        head_len += c.len_utf8();
+        head_used += used;
+    }
+    let (mut tail_chars, mut tail_used, mut tail_len) = (vec![], 0, 0);
+    for (c, used) in decode(tail).into_iter().rev() {
+        if tail_len + c.len_utf8() > tail.len() {
+            break;
+        }
+        tail_chars.push(c);
+        tail_len += c.len_utf8();
+        tail_used += used;
+    }
+    format!(
+        "{head_text}{}{}",
+        marker(len - (head_used + tail_used) as u64),
+        tail_chars.into_iter().rev().collect::<String>()
+    )
+}
+
+/// Keeps head and tail of `text` joined by the marker in `limit` bytes in total,
+/// returns `None` if `text` fits. Only the marker is kept if it alone does not fit
+pub fn truncate(text: &str, limit: usize) -> Option<String> {
+    if text.len() <= limit {
+        return None;
+    }
+    let kept = kept(text.len() as u64, limit);
+    let bytes = text.as_bytes();
+    Some(join(
+        &bytes[..kept / 2],
+        bytes.len() as u64,
+        &bytes[bytes.len() - (kept - kept / 2)..],
+    ))
+}
+
+/// Reads head and tail of `file` joined by the marker in `limit` bytes in total
+/// without reading the rest, returns text and whether it was truncated.
+/// Text which is not truncated may grow over `limit` by decoding invalid UTF-8
+pub async fn read_truncated(mut file: File, limit: usize) -> Result<(String, bool)> {
+    let len = file.metadata().await?.len();
+    if len <= limit as u64 {
+        let mut text = vec![];
+        file.read_to_end(&mut text).await?;
+        return Ok((String::from_utf8_lossy(&text).into_owned(), false));
+    }
+    let kept = kept(len, limit);
+    let mut head = vec![0; kept / 2];
+    file.read_exact(&mut head).await?;
+    let mut tail = vec![0; kept - kept / 2];
+    file.seek(SeekFrom::Start(len - tail.len() as u64)).await?;
+    file.read_exact(&mut tail).await?;
+    Ok((join(&head, len, &tail), true))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fitting_text_is_not_truncated() {
+        assert_eq!(truncate("", 0), None);
+        assert_eq!(truncate("abcdef", 6), None);
+        assert_eq!(truncate("abcdef", 100), None);
+    }
+
+    #[test]
+    fn truncated_text_fits_limit() {
+        let text = "a".repeat(100) + &"b".repeat(100);
+        let marker_len = marker(200).len();
+        for limit in marker_len..200 {
+            let truncated = truncate(&text, limit).unwrap();
+            assert!(truncated.len() <= limit, "limit {limit}: {truncated:?}");
+            assert!(truncated.len() + 1 >= limit, "limit {limit}: {truncated:?}");
+        }
+    }
+
+    #[test]
+    fn marker_reports_skipped_bytes() {
+        let text = "0123456789".repeat(10);
+        let limit = marker(100).len() + 10;
+        assert_eq!(
+            truncate(&text, limit).unwrap(),
+            "01234\n... 90 bytes truncated ...\n56789"
+        );
+        assert_eq!(
+            truncate(&text, 91).unwrap(),
+            format!("{}{}{}", &text[..31], marker(38), &text[69..])
+        );
+    }
+
+    #[test]
+    fn only_marker_is_kept_for_tiny_limit() {
+        assert_eq!(truncate("abcdef", 5).unwrap(), marker(6));
+    }
+
+    #[test]
+    fn truncation_keeps_chars_whole() {
+        let text = "й".repeat(100);
+        for limit in marker(200).len()..200 {
+            let truncated = truncate(&text, limit).unwrap();
+            assert!(truncated.len() <= limit);
+            assert!(!truncated.contains(char::REPLACEMENT_CHARACTER));
+        }
+    }
+
+    #[test]
+    fn split_chars_are_skipped() {
+        let text = "й".repeat(100).into_bytes();
+        for limit in marker(200).len()..200 {
+            let kept = kept(200, limit);
+            let joined = join(&text[..kept / 2], 200, &text[200 - (kept - kept / 2)..]);
+            assert!(joined.len() <= limit, "limit {limit}: {joined:?}");
+            assert!(!joined.contains(char::REPLACEMENT_CHARACTER));
+            let skipped: usize = joined
+                .split_once("... ")
+                .and_then(|(_, rest)| rest.split_once(' '))
+                .and_then(|(skipped, _)| skipped.parse().ok())
+                .unwrap();
+            assert_eq!(joined.len() - marker(skipped as u64).len() + skipped, 200);
+        }
+    }
+
+    #[test]
+    fn invalid_bytes_do_not_grow_text() {
+        let bytes = [0xff; 300];
+        let kept = kept(300, 100);
+        let joined = join(&bytes[..kept / 2], 300, &bytes[300 - (kept - kept / 2)..]);
+        assert!(joined.len() <= 100, "{joined:?}");
+        assert!(joined.contains(char::REPLACEMENT_CHARACTER));
+    }
+
+    #[test]
+    fn truncated_text_is_not_truncated_again() {
+        let text = "x".repeat(1000);
+        let truncated = truncate(&text, 100).unwrap();
+        assert_eq!(truncate(&truncated, 100), None);
+    }
+}
@@ -0,0 +1,72 @@
+use sha2::{Digest, Sha256};
+use tokio::fs::{File, create_dir_all, rename};
+
+use super::{Service, cache};
+use crate::{Result, prelude::*};
+
+const PROBLEMS_CACHE_DIR: &str = "problems";
+
+/// Hex encoded sha256 of problem package
+pub fn package_hash(data: &[u8]) -> String {
+    Sha256::digest(data)
+        .iter()
+        .map(|b| format!("{b:02x}"))
+        .collect()
+}
+
+fn is_hash(hash: &str) -> bool {
+    hash.len() == 64 && hash.bytes().all(|b| b.is_ascii_hexdigit())
+}
+
+impl Service {
+    fn problem_path(&self, hash: &str) -> String {
+        format!("{}/{PROBLEMS_CACHE_DIR}/{hash}.tar", self.cache_dir)
+    }
+
+    /// Saves problem package into cache, evicting least recently used packages
+    /// when cache is larger than `problems_cache_size`. Larger package is rejected
+    pub async fn add_problem(&self, hash: &str, data: &[u8]) -> Result<()> {
+        if !is_hash(hash) || package_hash(data) != hash.to_lowercase() {
+            bail!("problem package doesn't match hash '{hash}'");
+        }
+        // it would be evicted at once, and the manager would be asked for it again and again
+        if data.len() as u64 > self.config.problems_cache_size {
+            bail!(
+                "problem package of {} bytes is larger than problems_cache_size",
+                data.len()
+            );
+        }
+        let hash = hash.to_lowercase();
+        let _lock = self.problems_lock.lock().await;
+        create_dir_all(format!("{}/{PROBLEMS_CACHE_DIR}", self.cache_dir)).await?;
+
+        let path = self.problem_path(&hash);
+        let tmp_path = cache::tmp_path(&path);
+        tokio::fs::write(&tmp_path, data).await?;
+        rename(&tmp_path, &path).await?;
+        log::info!("problem '{hash}' cached");
+
+        self.evict_problems().await
+    }
+
+    /// Opens cached problem package and marks it as recently used
+    pub async fn open_problem(&self, hash: &str) -> Result<Option<File>> {
+        if !is_hash(hash) {
+            return Ok(None);
+        }
+        let path = self.problem_path(&hash.to_lowercase());
+        let _lock = self.problems_lock.lock().await;
+        let Ok(file) = File::open(&path).await else {
+            return Ok(None);
+        };
+        Ok(Some(cache::touch(file).await?))
+    }
+
+    async fn evict_problems(&self) -> Result<()> {
+        cache::evict(
+            &format!("{}/{PROBLEMS_CACHE_DIR}", self.cache_dir),
+            self.config.problems_cache_size,
+        )
+        .await
+    }
+}
@@ -0,0 +1,465 @@
+use std::{
+    collections::{BTreeMap, HashMap, VecDeque},
+    sync::{Arc, atomic::Ordering},
+};
+
+use tokio::{
+    sync::mpsc::UnboundedSender,
+    task::{AbortHandle, Id, JoinError, JoinSet},
+};
+
+use super::{
+    OperatorError, Service,
+    api::{
+        submission::{self, Task},
+        test,
+    },
+};
+use crate::{LogState, Result, prelude::*};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum GroupState {
+    Waiting,
+    Running,
+    Done,
+}
+
+/// Judging progress of groups, tests of group are queued when all its dependencies are done
+struct Schedule {
+    task: Arc<Task>,
+    sender: UnboundedSender<(usize, test::Result)>,
+
+    states: Box<[GroupState]>,
+    remaining: Box<[usize]>,
+    failed: Box<[bool]>,
+    lost: Box<[bool]>,
+    zeroed: Box<[bool]>,
+
+    queue: VecDeque<(usize, usize)>,
+    running: HashMap<Id, (usize, usize)>,
+    aborts: Box<[Vec<AbortHandle>]>,
+    tests_score: HashMap<usize, f64>,
+    tests_verdict: HashMap<usize, test::Verdict>,
+    max_time: f64,
+    max_memory: u64,
+}
+
+fn skipped(reason: &str) -> test::Result {
+    test::Result {
+        verdict: test::Verdict::Sk,
+        score: 0.,
+        time: 0.,
+        memory: 0,
+        output: Arc::from(""),
+        truncated: false,
+        message: Arc::from(reason),
+        comment: None,
+        transcript: None,
+    }
+}
+
+impl Schedule {
+    fn new(task: Arc<Task>, sender: UnboundedSender<(usize, test::Result)>) -> Schedule {
+        let count = task.groups.len();
+        Schedule {
+            states: vec![GroupState::Waiting; count].into_boxed_slice(),
+            remaining: task
+                .groups
+                .iter()
+                .map(|group| group.range.1 + 1 - group.range.0)
+                .collect(),
+            failed: vec![false; count].into_boxed_slice(),
+            lost: vec![false; count].into_boxed_slice(),
+            zeroed: vec![false; count].into_boxed_slice(),
+            queue: VecDeque::new(),
+            running: HashMap::new(),
+            aborts: vec![vec![]; count].into_boxed_slice(),
+            tests_score: HashMap::new(),
+            tests_verdict: HashMap::new(),
+            max_time: 0.,
+            max_memory: 0,
+            task,
+            sender,
+        }
+    }
+
+    /// Starts every waiting group whose dependencies are done,
+    /// a group with failed dependency is skipped entirely
+    fn release(&mut self) {
+        let task = Arc::clone(&self.task);
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for group in &task.groups {
+                if self.states[group.id] != GroupState::Waiting
+                    || group
+                        .depends
+                        .iter()
+                        .any(|depend| self.states[*depend] != GroupState::Done)
+                {
+                    continue;
+                }
+                changed = true;
+                let tests = (group.range.0 - 1)..group.range.1;
+                if let Some(depend) = group.depends.iter().find(|depend| self.failed[**depend]) {
+                    log::debug!("group {} skipped: group {depend} failed", group.id);
+                    self.failed[group.id] = true;
+                    self.zeroed[group.id] = true;
+                    self.states[group.id] = GroupState::Done;
+                    for test_number in tests {
+                        self.report(
+                            test_number,
+                            skipped(&format!("dependency group {depend} failed")),
+                        );
+                    }
+                } else if tests.is_empty() {
+                    self.states[group.id] = GroupState::Done;
+                } else {
+                    log::debug!("group {} started", group.id);
+                    self.states[group.id] = GroupState::Running;
+                    self.queue
+                        .extend(tests.map(|test_number| (group.id, test_number)));
+                }
+            }
+        }
+    }
+
+    fn report(&mut self, test_number: usize, result: test::Result) {
+        self.tests_score.insert(test_number, result.score);
+        self.tests_verdict.insert(test_number, result.verdict);
+        self.max_time = self.max_time.max(result.time);
+        self.max_memory = self.max_memory.max(result.memory);
+        self.sender.send((test_number + 1, result)).unwrap();
+    }
+
+    fn finish(&mut self, group_id: usize, test_number: usize, result: test::Result) {
+        if !self.lost[group_id] && self.task.groups[group_id].scoring.is_lost(result.score) {
+            log::debug!("group {group_id} lost on test {test_number}, cancelling its tests");
+            self.lost[group_id] = true;
+            for handle in &self.aborts[group_id] {
+                handle.abort();
+            }
+        }
+        self.report(test_number, result);
+
+        self.remaining[group_id] -= 1;
+        if self.remaining[group_id] == 0 {
+            // dependent groups fail only if the group earns nothing
+            let group = &self.task.groups[group_id];
+            if self.group_part(group) <= 0. {
+                log::debug!("group {group_id} failed");
+                self.failed[group_id] = true;
+            }
+            self.states[group_id] = GroupState::Done;
+            self.aborts[group_id].clear();
+            self.release();
+        }
+    }
+
+    fn joined(
+        &mut self,
+        joined: std::result::Result<(Id, Result<test::Result>), JoinError>,
+    ) -> Result<()> {
+        match joined {
+            Ok((id, result)) => {
+                let (group_id, test_number) = self.running.remove(&id).unwrap();
+                self.finish(group_id, test_number, result?);
+            }
+            Err(e) if e.is_cancelled() => {
+                let (group_id, test_number) = self.running.remove(&e.id()).unwrap();
+                self.finish(
+                    group_id,
+                    test_number,
+                    skipped("cancelled: group is already failed"),
+                );
+            }
+            Err(e) => return Err(e.into()),
+        }
+        Ok(())
+    }
+
+    /// Part of the group cost (0 ..= 1) earned by the group,
+    /// so that groups of zero cost (as samples) fail too
+    fn group_part(&self, group: &submission::Group) -> f64 {
+        if self.zeroed[group.id] {
+            return 0.;
+        }
+        let scores: Box<[f64]> = ((group.range.0 - 1)..group.range.1)
+            .map(|test_number| self.tests_score.get(&test_number).copied().unwrap_or(0.))
+            .collect();
+        group.scoring.score(1., &scores)
+    }
+
+    fn groups_score(&self) -> Box<[f64]> {
+        self.task
+            .groups
+            .iter()
+            .map(|group| group.cost * self.group_part(group))
+            .collect()
+    }
+
+    /// First failed test of every group prefers a real failure to a skipped test,
+    /// which may precede it when tests are cancelled
+    fn summary(&self) -> submission::Summary {
+        let mut verdicts = BTreeMap::new();
+        for verdict in self.tests_verdict.values() {
+            *verdicts.entry(*verdict).or_default() += 1;
+        }
+        let groups_failure = self
+            .task
+            .groups
+            .iter()
+            .map(|group| {
+                let failures: Vec<(usize, test::Verdict)> = ((group.range.0 - 1)..group.range.1)
+                    .filter_map(|test_number| {
+                        let verdict = *self.tests_verdict.get(&test_number)?;
+                        (!verdict.is_success()).then_some((test_number + 1, verdict))
+                    })
+                    .collect();
+                failures
+                    .iter()
+                    .find(|(_, verdict)| *verdict != test::Verdict::Sk)
+                    .or(failures.first())
+                    .copied()
+            })
+            .collect();
+        submission::Summary {
+            max_time: self.max_time,
+            max_memory: self.max_memory,
+            groups_failure,
+            verdicts,
+        }
+    }
+}
+
+impl Service {
+    /// Maximum of running tests of one job, so that sandboxes are shared equally between jobs.
+    /// Every job runs at least one test, boxes of a test are taken at once,
+    /// so tests over the pool wait for free boxes instead of blocking each other
+    fn sandboxes_share(&self, task: &Task) -> usize {
+        let per_test = match task.r#type {
+            submission::Type::Interactive => task.instances.len() + 1,
+            submission::Type::DoubleRun => 2,
+            submission::Type::Standard | submission::Type::OutputOnly => 1,
+        };
+        let jobs = self.active_jobs.load(Ordering::Relaxed).max(1);
+        (self.sandboxes.sandboxes_count() / jobs / per_test).max(1)
+    }
+
+    /// Runs tests of `task` in order of groups dependencies, returns score of every group
+    /// and summary of tests
+    pub(super) async fn run_tests(
+        &self,
+        work_dir: &str,
+        task: Arc<Task>,
+        sender: UnboundedSender<(usize, test::Result)>,
+    ) -> Result<(Box<[f64]>, submission::Summary)> {
+        let mut schedule = Schedule::new(Arc::clone(&task), sender);
+        let mut running = JoinSet::new();
+        schedule.release();
+
+        loop {
+            while let Some(joined) = running.try_join_next_with_id() {
+                schedule.joined(joined)?;
+            }
+
+            if running.len() < self.sandboxes_share(&task)
+                && let Some((group_id, test_number)) = schedule.queue.pop_front()
+            {
+                if schedule.lost[group_id] {
+                    schedule.finish(
+                        group_id,
+                        test_number,
+                        skipped("skipped: group is already failed"),
+                    );
+                    continue;
+                }
+
+                let log_state = LogState::new().push("test", &*format!("{test_number}"));
+                log::trace!("({log_state}) test started");
+                let enviroment = self
+                    .prepare(
+                        work_dir,
+                        Arc::clone(&task),
+                        self.lang_limits(&task, task.test_limits(group_id, test_number + 1)),
+                        test_number,
+                        log_state,
+                    )
+                    .await
+                    .context("enviroment preparing")?;
+                let handle = running
+                    .spawn(async move { enviroment.run().await.context("enviroment running") });
+                schedule
+                    .running
+                    .insert(handle.id(), (group_id, test_number));
+                schedule.aborts[group_id].push(handle);
+                continue;
+            }
+
+            let Some(joined) = running.join_next_with_id().await else {
+                break;
+            };
+            schedule.joined(joined)?;
+        }
+
+        if let Some(group) = schedule
+            .states
+            .iter()
+            .position(|state| *state != GroupState::Done)
+        {
+            return Err(OperatorError(
+                format!("group {group} is never started: dependencies are cyclic").into(),
+            )
+            .into());
+        }
+        Ok((schedule.groups_score(), schedule.summary()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::sync::mpsc::{UnboundedReceiver, unbounded_channel};
+
+    use super::*;
+
+    /// Groups of `(scoring, depends, tests count)` with consecutive tests
+    fn schedule(
+        groups: &[(&str, &[usize], usize)],
+    ) -> (Schedule, UnboundedReceiver<(usize, test::Result)>) {
+        let mut first = 1;
+        let groups: Vec<String> = groups
+            .iter()
+            .enumerate()
+            .map(|(id, (scoring, depends, count))| {
+                let group = format!(
+                    "- {{id: {id}, range: [{first}, {}], cost: 10, depends: {depends:?}, scoring: {scoring}}}",
+                    first + count - 1
+                );
+                first += count;
+                group
+            })
+            .collect();
+        let task = format!(
+            "type: standard\nlang: g++\nlimits: {{time: 1, real_time: 1, memory: 1024}}\ngroups:\n{}",
+            groups.join("\n")
+        );
+        let (sender, receiver) = unbounded_channel();
+        let mut schedule = Schedule::new(Arc::new(serde_yml::from_str(&task).unwrap()), sender);
+        schedule.release();
+        (schedule, receiver)
+    }
+
+    fn result(verdict: test::Verdict, score: f64) -> test::Result {
+        test::Result {
+            verdict,
+            score,
+            ..skipped("")
+        }
+    }
+
+    /// Finishes the next queued test with `verdict`
+    fn finish_next(schedule: &mut Schedule, verdict: test::Verdict, score: f64) -> usize {
+        let (group_id, test_number) = schedule.queue.pop_front().unwrap();
+        schedule.finish(group_id, test_number, result(verdict, score));
+        group_id
+    }
+
+    #[test]
+    fn dependency_chain_is_released_in_order() {
+        let (mut schedule, _receiver) = schedule(&[
+            ("all_or_nothing", &[], 1),
+            ("all_or_nothing", &[0], 1),
+            ("all_or_nothing", &[1], 1),
+        ]);
+        assert_eq!(finish_next(&mut schedule, test::Verdict::Ok, 1.), 0);
+        assert_eq!(finish_next(&mut schedule, test::Verdict::Ok, 1.), 1);
+        assert_eq!(finish_next(&mut schedule, test::Verdict::Ok, 1.), 2);
+        assert!(schedule.queue.is_empty());
+        assert_eq!(&*schedule.groups_score(), &[10., 10., 10.]);
+    }
+
+    #[test]
+    fn failed_dependency_skips_chain() {
+        let (mut schedule, mut receiver) = schedule(&[
+            ("all_or_nothing", &[], 1),
+            ("all_or_nothing", &[0], 1),
+            ("all_or_nothing", &[1], 1),
+            ("all_or_nothing", &[], 1),
+        ]);
+        assert_eq!(finish_next(&mut schedule, test::Verdict::Ok, 1.), 0);
+        assert_eq!(finish_next(&mut schedule, test::Verdict::Ok, 1.), 3);
+        assert_eq!(finish_next(&mut schedule, test::Verdict::Wa, 0.), 1);
+        assert!(schedule.queue.is_empty());
+        assert!(
+            schedule
+                .states
+                .iter()
+                .all(|state| *state == GroupState::Done)
+        );
+        assert_eq!(&*schedule.groups_score(), &[10., 0., 0., 10.]);
+
+        let mut verdicts = vec![];
+        while let Ok((test_id, result)) = receiver.try_recv() {
+            verdicts.push((test_id, result.verdict));
+        }
+        verdicts.sort_by_key(|(test_id, _)| *test_id);
+        assert_eq!(
+            verdicts,
+            [
+                (1, test::Verdict::Ok),
+                (2, test::Verdict::Wa),
+                (3, test::Verdict::Sk),
+                (4, test::Verdict::Ok),
+            ]
+        );
+    }
+
+    #[test]
+    fn partial_dependency_releases_chain() {
+        let (mut schedule, _receiver) =
+            schedule(&[("sum", &[], 1), ("max_of_tests", &[0], 1), ("sum", &[1], 1)]);
+        assert_eq!(finish_next(&mut schedule, test::Verdict::Pc, 0.5), 0);
+        assert_eq!(finish_next(&mut schedule, test::Verdict::Pc, 0.3), 1);
+        assert_eq!(finish_next(&mut schedule, test::Verdict::Ok, 1.), 2);
+        assert_eq!(&*schedule.groups_score(), &[5., 3., 10.]);
+    }
+
+    #[test]
+    fn partially_scored_dependency_releases_chain() {
+        let (mut schedule, mut receiver) =
+            schedule(&[("sum", &[], 2), ("all_or_nothing", &[0], 1)]);
+        assert_eq!(finish_next(&mut schedule, test::Verdict::Ok, 1.), 0);
+        assert_eq!(finish_next(&mut schedule, test::Verdict::Wa, 0.), 0);
+        assert_eq!(finish_next(&mut schedule, test::Verdict::Ok, 1.), 1);
+        assert!(schedule.queue.is_empty());
+        assert_eq!(&*schedule.groups_score(), &[5., 10.]);
+        while let Ok((_, result)) = receiver.try_recv() {
+            assert_ne!(result.verdict, test::Verdict::Sk);
+        }
+    }
+
+    #[test]
+    fn zero_scored_dependency_skips_chain() {
+        let (mut schedule, _receiver) = schedule(&[("max_of_tests", &[], 2), ("sum", &[0], 1)]);
+        assert_eq!(finish_next(&mut schedule, test::Verdict::Wa, 0.), 0);
+        assert_eq!(finish_next(&mut schedule, test::Verdict::Re, 0.), 0);
+        assert!(schedule.queue.is_empty());
+        assert_eq!(&*schedule.groups_score(), &[0., 0.]);
+    }
+
+    #[test]
+    fn lost_partial_dependency_skips_chain() {
+        let (mut schedule, _receiver) = schedule(&[("all_or_nothing", &[], 1), ("sum", &[0], 1)]);
+        assert_eq!(finish_next(&mut schedule, test::Verdict::Pc, 0.5), 0);
+        assert!(schedule.queue.is_empty());
+        assert_eq!(&*schedule.groups_score(), &[0., 0.]);
+    }
+
+    #[test]
+    fn wrong_answer_fails_dependency_of_sum_group() {
+        let (mut schedule, _receiver) = schedule(&[("sum", &[], 1), ("sum", &[0], 1)]);
+        assert_eq!(finish_next(&mut schedule, test::Verdict::Wa, 0.), 0);
+        assert!(schedule.queue.is_empty());
+        assert_eq!(&*schedule.groups_score(), &[0., 0.]);
+    }
+}
@@ -6,27 +6,41 @@ use tokio::{fs::File, io::AsyncReadExt as _, task::JoinHandle};
 use super::{
     Lang, SOLUTION_EXT, SOLUTION_NAME,
     api::{submission, test},
-    path_from,
+    path_from, payload,
 };
 use crate::{
     LogState, Result,
+    prelude::*,
     sandbox::{self, Command, MaybeLimited::*, RunStatus},
 };
 
-const CHECKER_NAME: &str = "checker";
-const CHECKER_EXT: Option<&str> = Some("out");
+pub(super) const CHECKER_NAME: &str = "checker";
+pub(super) const CHECKER_EXT: Option<&str> = Some("out");
 
-const INPUT_DIR: &str = "input";
-const INPUT_EXT: Option<&str> = Some("txt");
+pub(super) const INPUT_DIR: &str = "input";
+pub(super) const INPUT_EXT: Option<&str> = Some("txt");
 
-const CORRECT_DIR: &str = "correct";
-const CORRECT_EXT: Option<&str> = Some("txt");
+pub(super) const CORRECT_DIR: &str = "correct";
+pub(super) const CORRECT_EXT: Option<&str> = Some("txt");
+
+pub(super) const TARGET_INPUT_PATH: &str = "in.txt";
+pub(super) const TARGET_CORRECT_PATH: &str = "correct.txt";
+pub(super) const TARGET_OUTPUT_PATH: &str = "out.txt";
+const TARGET_CHECKER_OUTPUT_PATH: &str = "checker_out.txt";
+const TARGET_CHECKER_ERROR_PATH: &str = "checker_err.txt";
+const TARGET_CHECKER_RESULT_PATH: &str = "checker_result.xml";
+
+pub(super) const TARGET_CHECKER_PATH: &str = "checker.out";
+pub(super) const TARGET_SOLUTION_PATH: &str = "solution.out";
 
 pub struct Enviroment {
     sandbox: Arc<sandbox::Sandbox>,
     limits: submission::Limits,
     lang: Lang,
+    checker: submission::Checker,
+    checker_limits: submission::LimitsOverride,
 
+    data_limit: usize,
     work_dir: Box<str>,
     test_id: usize,
     log_state: Arc<LogState>,
@@ -36,6 +50,9 @@ pub async fn prepare(
     sandboxes: Arc<sandbox::Service>,
     lang: Lang,
     limits: submission::Limits,
+    checker: submission::Checker,
+    checker_limits: submission::LimitsOverride,
+    data_limit: usize,
     work_dir: Box<str>,
 
     test_id: usize,
@@ -49,6 +66,9 @@ pub async fn prepare(
         sandbox,
         lang,
         limits,
+        checker,
+        checker_limits,
+        data_limit,
         work_dir,
         test_id,
         log_state,
@@ -74,25 +94,19 @@ impl super::Enviroment for Enviroment {
 
         let src_solution_path = path_from(&self.work_dir, SOLUTION_NAME, SOLUTION_EXT);
 
-        const TARGET_INPUT_PATH: &str = "in.txt";
-        const TARGET_CORRECT_PATH: &str = "correct.txt";
-        const TARGET_OUTPUT_PATH: &str = "out.txt";
-        const TARGET_CHECKER_OUTPUT_PATH: &str = "checker_out.txt";
-        const TARGET_CHECKER_ERROR_PATH: &str = "checker_err.txt";
-
-        const TARGET_CHECKER_PATH: &str = "checker.out";
-        const TARGET_SOLUTION_PATH: &str = "solution.out";
-
+        let mut files = vec![
+            (File::open(&*src_input_path).await?, TARGET_INPUT_PATH),
+            (File::open(&*src_solution_path).await?, TARGET_SOLUTION_PATH),
+        ];
+        if self.checker == submission::Checker::Package {
+            files.push((File::open(&*src_checker_path).await?, TARGET_CHECKER_PATH));
+        }
         Arc::clone(&self.sandbox)
             .write_group_into_box(
-                vec![
-                    (File::open(&*src_input_path).await?, TARGET_INPUT_PATH),
-                    (File::open(&*src_checker_path).await?, TARGET_CHECKER_PATH),
-                    (File::open(&*src_solution_path).await?, TARGET_SOLUTION_PATH),
-                ]
-                .into_iter()
-                .map(|(from, to)| (from, Box::from(to)))
-                .collect(),
+                files
+                    .into_iter()
+                    .map(|(from, to)| (from, Box::from(to)))
+                    .collect(),
             )
             .await?;
 
@@ -118,17 +132,21 @@ impl super::Enviroment for Enviroment {
             }
         };
 
-        let mut output_file = self.sandbox.read_from_box(TARGET_OUTPUT_PATH).await?;
-        let mut output = String::new();
-        output_file.read_to_string(&mut output).await?;
-        let output = Arc::from(output.as_str());
+        let (output, truncated) = payload::read_truncated(
+            self.sandbox.read_from_box(TARGET_OUTPUT_PATH).await?,
+            self.data_limit,
+        )
+        .await?;
+        let output: Arc<str> = Arc::from(output);
 
         if let Some(verdict) = test::Verdict::from_run_status(solution_result.status) {
             return Ok(test::Result {
                 verdict,
+                score: 0.,
                 time: solution_result.time,
                 memory: solution_result.memory,
                 output,
+                truncated,
                 message: Arc::from(
                     format!(
                         "ISOLATE: {}",
@@ -136,6 +154,8 @@ impl super::Enviroment for Enviroment {
                     )
                     .as_str(),
                 ),
+                comment: None,
+                transcript: None,
             });
         }
 
@@ -147,77 +167,23 @@ impl super::Enviroment for Enviroment {
             log::debug!("({log_state}) correct file not founded");
         }
 
-        let mut checker_cmd = Command::new(format!("./{TARGET_CHECKER_PATH}"));
-        checker_cmd
-            .args([TARGET_INPUT_PATH, TARGET_OUTPUT_PATH, TARGET_CORRECT_PATH])
-            .count_files(Unlimited)
-            .count_process(Unlimited)
-            .stdout(TARGET_CHECKER_OUTPUT_PATH)
-            .stderr(TARGET_CHECKER_ERROR_PATH);
-
-        let checker_result = match self.sandbox.run(&checker_cmd).await {
-            Ok(res) => res,
-            Err(e) => {
-                log::error!("({log_state}) checker error: {e:?}");
-                return Err(e);
-            }
-        };
-
-        let sandbox_clone = Arc::clone(&self.sandbox);
-        let checker_output_handler: JoinHandle<Result<String>> = tokio::spawn(async move {
-            let mut output = String::new();
-            sandbox_clone
-                .read_from_box(TARGET_CHECKER_OUTPUT_PATH)
-                .await?
-                .read_to_string(&mut output)
-                .await?;
-            Ok(output)
-        });
-
-        let sandbox_clone = Arc::clone(&self.sandbox);
-        let checker_error_handler: JoinHandle<Result<String>> = tokio::spawn(async move {
-            let mut output = String::new();
-            sandbox_clone
-                .read_from_box(TARGET_CHECKER_ERROR_PATH)
-                .await?
-                .read_to_string(&mut output)
-                .await?;
-            Ok(output)
-        });
-
-        let checker_output = checker_output_handler.await?.unwrap_or("-".to_string());
-        let checker_error = checker_error_handler.await?.unwrap_or("-".to_string());
-
-        let (verdict, message) = match checker_result.status {
-            RunStatus::Ml | RunStatus::Tl | RunStatus::Sg(_) => (
-                test::Verdict::Te,
-                format!(
-                    "checker_output: {checker_output}\n, checker_error: {checker_error}\n 'isolate': {}",
-                    checker_result.status_message.as_deref().unwrap_or("")
-                ),
-            ),
-            RunStatus::Ok => (
-                test::Verdict::Ok,
-                format!(
-                    "checker_output: {checker_output}\n, checker_error: {checker_error}\n 'isolate': {}",
-                    checker_result.status_message.as_deref().unwrap_or("")
-                ),
-            ),
-            RunStatus::Re(code) => (
-                match code {
-                    1 => test::Verdict::Wa,
-                    2 => test::Verdict::Pe,
-                    _ => test::Verdict::Te,
-                },
-                format!("checker_output: {checker_output}\n, checker_error: {checker_error}"),
-            ),
-        };
+        let (verdict, score, message, comment) = check(
+            Arc::clone(&self.sandbox),
+            self.checker,
+            self.checker_limits,
+            &log_state,
+        )
+        .await?;
 
         let result = test::Result {
             verdict,
+            score,
             message: Arc::from(message),
+            comment,
+            transcript: None,
 
             output,
+            truncated,
             memory: solution_result.memory,
             time: solution_result.time,
         };
@@ -227,3 +193,369 @@ impl super::Enviroment for Enviroment {
         Ok(result)
     }
 }
+
+/// Runs checker on `TARGET_INPUT_PATH`, `TARGET_OUTPUT_PATH` and `TARGET_CORRECT_PATH`,
+/// which must be already placed in the box together with `TARGET_CHECKER_PATH`
+/// (not needed for builtin checker)
+pub(super) async fn check(
+    sandbox: Arc<sandbox::Sandbox>,
+    checker: submission::Checker,
+    limits: submission::LimitsOverride,
+    log_state: &LogState,
+) -> Result<(test::Verdict, f64, String, Option<test::Comment>)> {
+    if let submission::Checker::Builtin(builtin) = checker {
+        let mut output = vec![];
+        sandbox
+            .read_from_box(TARGET_OUTPUT_PATH)
+            .await?
+            .read_to_end(&mut output)
+            .await?;
+        let mut correct = vec![];
+        sandbox
+            .read_from_box(TARGET_CORRECT_PATH)
+            .await
+            .context("correct file reading")?
+            .read_to_end(&mut correct)
+            .await?;
+
+        let (verdict, score, text) = builtin.check(&output, &correct);
+        log::trace!("({log_state}) builtin checker {builtin:?}: {text}");
+        let comment = test::Comment {
+            outcome: Box::from(match verdict {
+                test::Verdict::Ok => "accepted",
+                test::Verdict::Fl => "fail",
+                _ => "wrong-answer",
+            }),
+            points: None,
+            text: text.into_boxed_str(),
+        };
+        return Ok((
+            verdict,
+            score,
+            format!("builtin checker {builtin:?}"),
+            Some(comment),
+        ));
+    }
+
+    let mut checker_cmd = Command::new(format!("./{TARGET_CHECKER_PATH}"));
+    checker_cmd
+        .args([
+            TARGET_INPUT_PATH,
+            TARGET_OUTPUT_PATH,
+            TARGET_CORRECT_PATH,
+            TARGET_CHECKER_RESULT_PATH,
+            "-appes",
+        ])
+        .count_files(Unlimited)
+        .count_process(Unlimited)
+        .stdout(TARGET_CHECKER_OUTPUT_PATH)
+        .stderr(TARGET_CHECKER_ERROR_PATH);
+    limit_command(&mut checker_cmd, &limits);
+
+    let checker_result = match sandbox.run(&checker_cmd).await {
+        Ok(res) => res,
+        Err(e) => {
+            log::error!("({log_state}) checker error: {e:?}");
+            return Err(e);
+        }
+    };
+
+    let sandbox_clone = Arc::clone(&sandbox);
+    let checker_output_handler: JoinHandle<Result<String>> = tokio::spawn(async move {
+        let mut output = String::new();
+        sandbox_clone
+            .read_from_box(TARGET_CHECKER_OUTPUT_PATH)
+            .await?
+            .read_to_string(&mut output)
+            .await?;
+        Ok(output)
+    });
+
+    let sandbox_clone = Arc::clone(&sandbox);
+    let checker_error_handler: JoinHandle<Result<String>> = tokio::spawn(async move {
+        let mut output = String::new();
+        sandbox_clone
+            .read_from_box(TARGET_CHECKER_ERROR_PATH)
+            .await?
+            .read_to_string(&mut output)
+            .await?;
+        Ok(output)
+    });
+
+    let checker_output = checker_output_handler.await?.unwrap_or("-".to_string());
+    let checker_error = checker_error_handler.await?.unwrap_or("-".to_string());
+    let comment = sandbox
+        .read_string_from_box(TARGET_CHECKER_RESULT_PATH)
+        .await
+        .ok()
+        .and_then(|xml| parse_testlib_result(&xml));
+
+    let mut message = format!(
+        "checker_output: {checker_output}\n, checker_error: {checker_error}\n 'isolate': {}",
+        checker_result.status_message.as_deref().unwrap_or("")
+    );
+    if let Some(failure) = exceeded_limits(checker_result.status) {
+        message = format!("checker {failure}\n{message}");
+    }
+    let (verdict, score) = match (checker_result.status, &comment) {
+        (RunStatus::Ml | RunStatus::Tl | RunStatus::Sg(_), _) => (test::Verdict::Te, 0.),
+        (_, Some(comment)) => outcome_verdict(comment),
+        (RunStatus::Ok, None) => (test::Verdict::Ok, 1.),
+        (RunStatus::Re(code), None) => testlib_verdict(code, &checker_error, &checker_output),
+    };
+
+    Ok((verdict, score, message, comment))
+}
+
+/// Sets limits of checker (interactor), limits which are not set stay sandbox defaults
+pub(super) fn limit_command(cmd: &mut Command, limits: &submission::LimitsOverride) {
+    if let Some(time) = limits.time {
+        cmd.time(Limited(time));
+    }
+    if let Some(real_time) = limits.real_time {
+        cmd.real_time(Limited(real_time));
+    }
+    if let Some(memory) = limits.memory {
+        cmd.memory(Limited(memory));
+    }
+    if let Some(stack) = limits.stack {
+        cmd.stack(Limited(stack));
+    }
+}
+
+/// Explains run status of checker (interactor), which is a judge failure
+pub(super) fn exceeded_limits(status: RunStatus) -> Option<String> {
+    match status {
+        RunStatus::Tl => Some("exceeded time limit".to_string()),
+        RunStatus::Ml => Some("exceeded memory limit".to_string()),
+        RunStatus::Sg(signal) => Some(format!("was killed by signal {signal}")),
+        RunStatus::Ok | RunStatus::Re(_) => None,
+    }
+}
+
+const TESTLIB_FAIL: u8 = 3;
+const TESTLIB_DIRT: u8 = 4;
+const TESTLIB_POINTS: u8 = 7;
+const TESTLIB_UNEXPECTED_EOF: u8 = 8;
+// `_pc(n)` exits with `PC_BASE_EXIT_CODE + n`, which is not ambiguous only
+// for checkers compiled with `TESTSYS` (base 50), default base 0 collides with other codes
+const TESTLIB_PC_BASE: u8 = 50;
+const TESTLIB_PC_MAX: u8 = TESTLIB_PC_BASE + 100;
+
+/// Test score is a fraction of the test, absolute points are clamped
+fn partial(score: f64) -> (test::Verdict, f64) {
+    if !(0. ..=1.).contains(&score) {
+        log::warn!("checker test score {score} is out of 0 ..= 1, it is clamped");
+    }
+    let score = score.clamp(0., 1.);
+    (
+        if score >= 1. {
+            test::Verdict::Ok
+        } else if score > 0. {
+            test::Verdict::Pc
+        } else {
+            test::Verdict::Wa
+        },
+        score,
+    )
+}
+
+/// Maps non-zero exit code of __testlib__ checker (interactor) to verdict and test score (0 ..= 1).
+/// `_pc(n)` is `n` percents of the test, `points` is read from beginning of checker messages
+/// written by `quitp` as `points <value> <message>`
+pub(super) fn testlib_verdict(code: u8, error: &str, output: &str) -> (test::Verdict, f64) {
+    match code {
+        1 => (test::Verdict::Wa, 0.),
+        2 | TESTLIB_DIRT | TESTLIB_UNEXPECTED_EOF => (test::Verdict::Pe, 0.),
+        TESTLIB_FAIL => (test::Verdict::Fl, 0.),
+        TESTLIB_POINTS => [error, output]
+            .into_iter()
+            .find_map(|message| {
+                let mut words = message.split_whitespace().peekable();
+                words.next_if_eq(&"points");
+                words.next()?.parse::<f64>().ok()
+            })
+            .map(partial)
+            .unwrap_or((test::Verdict::Te, 0.)),
+        TESTLIB_PC_BASE..=TESTLIB_PC_MAX => partial((code - TESTLIB_PC_BASE) as f64 / 100.),
+        _ => (test::Verdict::Te, 0.),
+    }
+}
+
+fn outcome_verdict(comment: &test::Comment) -> (test::Verdict, f64) {
+    match &*comment.outcome {
+        "accepted" => (test::Verdict::Ok, 1.),
+        "wrong-answer" => (test::Verdict::Wa, 0.),
+        "presentation-error" | "wrong-output-format" | "unexpected-eof" => (test::Verdict::Pe, 0.),
+        "fail" => (test::Verdict::Fl, 0.),
+        "points" | "relative-scoring" | "partially-correct" => comment
+            .points
+            .map(partial)
+            .unwrap_or((test::Verdict::Te, 0.)),
+        _ => (test::Verdict::Te, 0.),
+    }
+}
+
+/// Parses `<result outcome = "..." points = "...">text</result>`
+/// written by __testlib__ with `-appes` flag
+fn parse_testlib_result(xml: &str) -> Option<test::Comment> {
+    let start = xml.find("<result")? + "<result".len();
+    let end = start + xml[start..].find('>')?;
+    let attributes = &xml[start..end];
+    let text_end = end + 1 + xml[end + 1..].find("</result>")?;
+
+    let attribute = |name: &str| {
+        let mut rest = attributes;
+        loop {
+            let (key, value) = rest.split_once('=')?;
+            let (value, other) = value.trim_start().strip_prefix('"')?.split_once('"')?;
+            if key.trim() == name {
+                return Some(value);
+            }
+            rest = other;
+        }
+    };
+
+    let outcome = attribute("outcome")?;
+    let points = attribute("points").and_then(|points| points.parse().ok());
+    let points = if outcome == "partially-correct" {
+        attribute("pctype")
+            .and_then(|percents| percents.parse::<f64>().ok())
+            .map(|percents| percents / 100.)
+            .or(points)
+    } else {
+        points
+    };
+    let text = xml[end + 1..text_end]
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&apos;", "'")
+        .replace("&amp;", "&");
+
+    Some(test::Comment {
+        outcome: Box::from(outcome),
+        points,
+        text: text.into_boxed_str(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn testlib_exit_codes() {
+        assert_eq!(testlib_verdict(1, "", ""), (test::Verdict::Wa, 0.));
+        assert_eq!(testlib_verdict(2, "", ""), (test::Verdict::Pe, 0.));
+        assert_eq!(testlib_verdict(3, "", ""), (test::Verdict::Fl, 0.));
+        assert_eq!(testlib_verdict(4, "", ""), (test::Verdict::Pe, 0.));
+        assert_eq!(testlib_verdict(8, "", ""), (test::Verdict::Pe, 0.));
+        assert_eq!(testlib_verdict(16, "", ""), (test::Verdict::Te, 0.));
+        assert_eq!(testlib_verdict(200, "", ""), (test::Verdict::Te, 0.));
+    }
+
+    #[test]
+    fn testlib_partially_correct() {
+        assert_eq!(testlib_verdict(50, "", ""), (test::Verdict::Wa, 0.));
+        assert_eq!(testlib_verdict(75, "", ""), (test::Verdict::Pc, 0.25));
+        assert_eq!(testlib_verdict(150, "", ""), (test::Verdict::Ok, 1.));
+        assert_eq!(testlib_verdict(151, "", ""), (test::Verdict::Te, 0.));
+    }
+
+    #[test]
+    fn testlib_points() {
+        // stderr of `quitp(0.5, "found %d of %d", 1, 2)`
+        assert_eq!(
+            testlib_verdict(7, "points 0.5 found 1 of 2\n", ""),
+            (test::Verdict::Pc, 0.5)
+        );
+        // stderr of `quitp(1.0)`
+        assert_eq!(
+            testlib_verdict(7, "points 1\n", ""),
+            (test::Verdict::Ok, 1.)
+        );
+        assert_eq!(
+            testlib_verdict(7, "points 0\n", ""),
+            (test::Verdict::Wa, 0.)
+        );
+        assert_eq!(
+            testlib_verdict(7, "", "0.25 partial"),
+            (test::Verdict::Pc, 0.25)
+        );
+        assert_eq!(testlib_verdict(7, "points\n", ""), (test::Verdict::Te, 0.));
+        assert_eq!(testlib_verdict(7, "", ""), (test::Verdict::Te, 0.));
+    }
+
+    fn verdict_of(xml: &str) -> (test::Verdict, f64) {
+        outcome_verdict(&parse_testlib_result(xml).unwrap())
+    }
+
+    #[test]
+    fn testlib_result_outcomes() {
+        let xml = "<?xml version=\"1.0\" encoding=\"windows-1251\"?>\
+            <result outcome = \"accepted\">ok 3 numbers</result>";
+        let comment = parse_testlib_result(xml).unwrap();
+        assert_eq!(&*comment.outcome, "accepted");
+        assert_eq!(comment.points, None);
+        assert_eq!(&*comment.text, "ok 3 numbers");
+        assert_eq!(outcome_verdict(&comment), (test::Verdict::Ok, 1.));
+
+        assert_eq!(
+            verdict_of("<result outcome = \"wrong-answer\">1st numbers differ</result>"),
+            (test::Verdict::Wa, 0.)
+        );
+        assert_eq!(
+            verdict_of("<result outcome = \"presentation-error\">eof</result>"),
+            (test::Verdict::Pe, 0.)
+        );
+        assert_eq!(
+            verdict_of("<result outcome = \"fail\">bad answer</result>"),
+            (test::Verdict::Fl, 0.)
+        );
+        assert_eq!(
+            verdict_of("<result outcome = \"unknown\"></result>"),
+            (test::Verdict::Te, 0.)
+        );
+    }
+
+    #[test]
+    fn testlib_result_points() {
+        assert_eq!(
+            verdict_of("<result outcome = \"points\" points = \"0.5\">half</result>"),
+            (test::Verdict::Pc, 0.5)
+        );
+        assert_eq!(
+            verdict_of("<result outcome = \"points\" points = \"5\">absolute</result>"),
+            (test::Verdict::Ok, 1.)
+        );
+        assert_eq!(
+            verdict_of("<result outcome = \"points\">no points</result>"),
+            (test::Verdict::Te, 0.)
+        );
+        assert_eq!(
+            verdict_of("<result outcome = \"partially-correct\" pctype = \"30\">pc</result>"),
+            (test::Verdict::Pc, 0.3)
+        );
+        assert_eq!(
+            verdict_of("<result outcome = \"partially-correct\" points = \"0.2\">pc</result>"),
+            (test::Verdict::Pc, 0.2)
+        );
+    }
+
+    #[test]
+    fn testlib_result_text_is_unescaped() {
+        let comment = parse_testlib_result(
+            "<result outcome = \"wrong-answer\">&lt;a&gt; &amp;&quot;b&apos;</result>",
+        )
+        .unwrap();
+        assert_eq!(&*comment.text, "<a> &\"b'");
+    }
+
+    #[test]
+    fn incomplete_testlib_result() {
+        assert!(parse_testlib_result("").is_none());
+        assert!(parse_testlib_result("<result outcome = \"accepted\">ok").is_none());
+        assert!(parse_testlib_result("<result>ok</result>").is_none());
+    }
+}
@@ -0,0 +1,113 @@
+use std::{
+    sync::{Arc, Mutex},
+    time::Instant,
+};
+
+use tokio::{
+    fs::File,
+    io::{AsyncReadExt as _, AsyncWriteExt as _},
+};
+
+use crate::Result;
+
+const BUFFER_SIZE: usize = 4096;
+const TRUNCATED_MARKER: &str = "... transcript is truncated\n";
+
+/// Timestamped record of data passed through proxies, stops growing after `limit` bytes
+pub struct Transcript {
+    start: Instant,
+    limit: usize,
+    text: String,
+    truncated: bool,
+}
+
+impl Transcript {
+    pub fn new(limit: usize) -> Transcript {
+        Transcript {
+            start: Instant::now(),
+            limit,
+            text: String::new(),
+            truncated: false,
+        }
+    }
+
+    fn record(&mut self, direction: &str, data: &[u8]) {
+        let time = self.start.elapsed().as_secs_f64();
+        for line in String::from_utf8_lossy(data).split_inclusive('\n') {
+            if self.truncated {
+                return;
+            }
+            let entry = format!(
+                "[{time:>9.3}] {direction}: {}\n",
+                line.strip_suffix('\n').unwrap_or(line)
+            );
+            // room for the marker is always kept, so the transcript never exceeds `limit`
+            if self.text.len() + entry.len() + TRUNCATED_MARKER.len() > self.limit {
+                self.text.push_str(TRUNCATED_MARKER);
+                self.truncated = true;
+            } else {
+                self.text.push_str(&entry);
+            }
+        }
+    }
+
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+}
+
+/// Passes data from FIFO `from` to FIFO `to` as soon as it is read, recording it into `transcript`.
+/// Ends on EOF, when nobody keeps `from` opened for writing
+pub async fn proxy(
+    from: Box<str>,
+    to: Box<str>,
+    direction: Box<str>,
+    transcript: Arc<Mutex<Transcript>>,
+) -> Result<()> {
+    let mut reader = File::open(&*from).await?;
+    let mut writer = Some(File::options().write(true).open(&*to).await?);
+    let mut buffer = vec![0; BUFFER_SIZE];
+    loop {
+        let count = reader.read(&mut buffer).await?;
+        if count == 0 {
+            return Ok(());
+        }
+        transcript
+            .lock()
+            .unwrap()
+            .record(&direction, &buffer[..count]);
+        // the receiver may have exited, the rest is still recorded
+        if let Some(file) = &mut writer
+            && (file.write_all(&buffer[..count]).await.is_err() || file.flush().await.is_err())
+        {
+            writer = None;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn entries_are_timestamped_by_line() {
+        let mut transcript = Transcript::new(1 << 10);
+        transcript.record("0 -> interactor", b"1 2\n3");
+        let lines: Vec<&str> = transcript.text().lines().collect();
+        assert_eq!(lines.len(), 2);
+        assert!(lines[0].ends_with("] 0 -> interactor: 1 2"));
+        assert!(lines[1].ends_with("] 0 -> interactor: 3"));
+    }
+
+    #[test]
+    fn truncated_transcript_fits_limit() {
+        for limit in TRUNCATED_MARKER.len()..200 {
+            let mut transcript = Transcript::new(limit);
+            for _ in 0..20 {
+                transcript.record("interactor -> 0", b"12345\n");
+            }
+            assert!(transcript.text().len() <= limit, "limit {limit}");
+            assert!(transcript.text().ends_with(TRUNCATED_MARKER));
+        }
+    }
+}
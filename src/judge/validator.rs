@@ -0,0 +1,164 @@
+use std::sync::Arc;
+
+use tokio::{fs::File, task::JoinHandle};
+
+use super::{OperatorError, api::submission::Task, path_from, tests_location};
+use crate::{
+    LogState, Result,
+    sandbox::{self, Command, MaybeLimited::*, RunStatus},
+};
+
+const VALIDATOR_NAME: &str = "validator";
+const VALIDATOR_EXT: Option<&str> = Some("out");
+
+const TARGET_VALIDATOR_PATH: &str = "validator.out";
+const TARGET_INPUT_PATH: &str = "in.txt";
+const TARGET_VALIDATOR_OUTPUT_PATH: &str = "validator_out.txt";
+const TARGET_VALIDATOR_ERROR_PATH: &str = "validator_err.txt";
+
+/// Runs package validator on every test of `task`, if validator exists.
+/// Fails with `OperatorError` listing all invalid tests
+pub async fn validate(sandboxes: Arc<sandbox::Service>, work_dir: &str, task: &Task) -> Result<()> {
+    let src_validator_path = path_from(work_dir, VALIDATOR_NAME, VALIDATOR_EXT);
+    if !tokio::fs::try_exists(&*src_validator_path).await? {
+        log::debug!("validator not founded, validation skipped");
+        return Ok(());
+    }
+
+    let (tests_dir, tests_ext) = tests_location(&task.r#type);
+
+    let mut handlers: Vec<(usize, JoinHandle<Result<Option<String>>>)> = vec![];
+    for group in &task.groups {
+        for test_id in (group.range.0 - 1)..group.range.1 {
+            let src_test_path = path_from(
+                &format!("{work_dir}/{tests_dir}"),
+                &format!("{}", test_id + 1),
+                tests_ext,
+            );
+            let src_validator_path = src_validator_path.clone();
+            let sandboxes = Arc::clone(&sandboxes);
+            let group_id = group.id;
+            let log_state = LogState::new().push("test", &*format!("{test_id}"));
+            handlers.push((
+                test_id,
+                tokio::spawn(async move {
+                    validate_test(
+                        sandboxes,
+                        &src_validator_path,
+                        &src_test_path,
+                        Some(group_id),
+                        log_state,
+                    )
+                    .await
+                }),
+            ));
+        }
+    }
+
+    let mut problems = vec![];
+    for (test_id, handler) in handlers {
+        if let Some(message) = handler.await?? {
+            problems.push(format!("test {}: {message}", test_id + 1));
+        }
+    }
+
+    if !problems.is_empty() {
+        return Err(OperatorError(
+            format!("tests validation failed:\n{}", problems.join("\n")).into_boxed_str(),
+        )
+        .into());
+    }
+    log::info!("all tests are valid");
+    Ok(())
+}
+
+/// Runs package validator on single test `src_test_path`, if validator exists.
+/// Returns validator message if test is invalid
+pub async fn validate_input(
+    sandboxes: Arc<sandbox::Service>,
+    work_dir: &str,
+    src_test_path: &str,
+    group_id: Option<usize>,
+) -> Result<Option<String>> {
+    let src_validator_path = path_from(work_dir, VALIDATOR_NAME, VALIDATOR_EXT);
+    if !tokio::fs::try_exists(&*src_validator_path).await? {
+        log::debug!("validator not founded, validation skipped");
+        return Ok(None);
+    }
+    validate_test(
+        sandboxes,
+        &src_validator_path,
+        src_test_path,
+        group_id,
+        LogState::new(),
+    )
+    .await
+}
+
+/// Returns validator message if test is invalid
+async fn validate_test(
+    sandboxes: Arc<sandbox::Service>,
+    src_validator_path: &str,
+    src_test_path: &str,
+    group_id: Option<usize>,
+    log_state: Arc<LogState>,
+) -> Result<Option<String>> {
+    let Ok(test_file) = File::open(src_test_path).await else {
+        return Ok(Some("test file not found".to_string()));
+    };
+
+    let sandbox = Arc::new(sandboxes.initialize_sandbox().await?);
+    let log_state = log_state.push("box_id", &*format!("{}", sandbox.id()));
+
+    Arc::clone(&sandbox)
+        .write_group_into_box(
+            vec![
+                (test_file, TARGET_INPUT_PATH),
+                (File::open(src_validator_path).await?, TARGET_VALIDATOR_PATH),
+            ]
+            .into_iter()
+            .map(|(from, to)| (from, Box::from(to)))
+            .collect(),
+        )
+        .await?;
+
+    let mut validator_cmd = Command::new(format!("./{TARGET_VALIDATOR_PATH}"));
+    if let Some(group_id) = group_id {
+        validator_cmd.args(["--group", &group_id.to_string()]);
+    }
+    validator_cmd
+        .count_files(Unlimited)
+        .count_process(Unlimited)
+        .stdin(TARGET_INPUT_PATH)
+        .stdout(TARGET_VALIDATOR_OUTPUT_PATH)
+        .stderr(TARGET_VALIDATOR_ERROR_PATH);
+
+    let validator_result = match sandbox.run(&validator_cmd).await {
+        Ok(res) => res,
+        Err(e) => {
+            log::error!("({log_state}) validator error: {e:?}");
+            return Err(e);
+        }
+    };
+
+    if validator_result.status == RunStatus::Ok {
+        log::trace!("({log_state}) test is valid");
+        return Ok(None);
+    }
+
+    let validator_output = sandbox
+        .read_string_from_box(TARGET_VALIDATOR_OUTPUT_PATH)
+        .await
+        .unwrap_or("-".to_string());
+    let validator_error = sandbox
+        .read_string_from_box(TARGET_VALIDATOR_ERROR_PATH)
+        .await
+        .unwrap_or("-".to_string());
+
+    log::debug!("({log_state}) test is invalid: {validator_error}");
+
+    Ok(Some(format!(
+        "validator_output: {validator_output}\n, validator_error: {validator_error}\n 'isolate': {}",
+        validator_result.status_message.as_deref().unwrap_or("")
+    )))
+}
@@ -32,7 +32,13 @@ pub use {
     serde::{Deserialize, Serialize},
 };
 
-use {std::sync::Arc, uuid::Uuid};
+use {
+    std::{
+        collections::HashMap,
+        sync::{Arc, Mutex},
+    },
+    uuid::Uuid,
+};
 
 #[derive(Clone, Deserialize, Debug)]
 struct Config {
@@ -105,6 +111,7 @@ async fn main() -> Result<()> {
     }
 
     let judger_work_dir = format!("{}/judge", config.work_dir).into_boxed_str();
+    let judger_cache_dir = format!("{}/cache", config.work_dir).into_boxed_str();
     let token = Uuid::new_v4();
     println!("\n[{}] invoker token\n", format!("{token}").yellow().bold());
 
@@ -117,19 +124,29 @@ async fn main() -> Result<()> {
         receiver,
         sender,
         judge_service: Arc::new(
-            judge::Service::new(&config.config_dir, isolate_service, judger_work_dir).await,
+            judge::Service::new(
+                &config.config_dir,
+                isolate_service,
+                judger_work_dir,
+                judger_cache_dir,
+            )
+            .await,
         ),
         cert: Arc::new(cert),
+        submissions: Mutex::new(HashMap::new()),
     };
 
     let app = Arc::new(app);
     let result = Arc::clone(&app).run();
     for name in std::env::args().skip(1) {
         app.start_judgment(
-            tokio::fs::read(name.as_str())
-                .await
-                .context("reading file '{name}'")?
-                .into_boxed_slice(),
+            Box::from(name.as_str()),
+            Box::new(
+                tokio::fs::File::open(name.as_str())
+                    .await
+                    .context("reading file '{name}'")?,
+            ),
+            None,
         );
     }
 
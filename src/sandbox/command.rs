@@ -67,7 +67,7 @@ impl Command {
     }
 
     pub fn stderr(&mut self, path: impl AsRef<str>) -> &mut Self {
-        self.stdout = Some(Box::from(path.as_ref()));
+        self.stderr = Some(Box::from(path.as_ref()));
         self
     }
 
@@ -108,12 +108,12 @@ impl Command {
     }
 
     pub fn open_dir(&mut self, path: impl AsRef<str>) -> &mut Self {
-        self.args.push(Box::from(path.as_ref()));
+        self.open_dirs.push(Box::from(path.as_ref()));
         self
     }
     pub fn open_dirs(&mut self, paths: impl IntoIterator<Item = impl AsRef<str>>) -> &mut Self {
         for path in paths {
-            self.arg(path);
+            self.open_dir(path);
         }
         self
     }
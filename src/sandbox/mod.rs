@@ -15,8 +15,9 @@ use resource_pool::ResourcePool;
 use serde::{Deserialize, Serialize};
 use tokio::{
     fs::File,
-    io::{AsyncRead, AsyncWriteExt},
+    io::{AsyncRead, AsyncReadExt, AsyncWriteExt},
     process::Command as TokioCommand,
+    sync::Mutex,
 };
 
 #[derive(Clone, Copy, Debug, Serialize, Deserialize)]
@@ -116,9 +117,15 @@ pub struct Service {
     config: IsolateConfig,
     path: Box<str>,
     boxes_pull: ResourcePool<usize>,
+    /// Held while taking several boxes, so that two tests never wait for each other's boxes
+    group_taking: Mutex<()>,
 }
 
 impl Service {
+    pub fn sandboxes_count(&self) -> usize {
+        self.config.sandboxes_count
+    }
+
     pub async fn new(config_dir: &str, path: Box<str>) -> Result<Arc<Service>> {
         if !TokioCommand::new(&*path)
             .arg("--version")
@@ -136,6 +143,7 @@ impl Service {
 
         Ok(Arc::new(Service {
             boxes_pull: (0..config.sandboxes_count).collect(),
+            group_taking: Mutex::new(()),
             config,
             path,
         }))
@@ -143,6 +151,42 @@ impl Service {
 
     pub async fn initialize_sandbox(self: Arc<Self>) -> Result<Sandbox> {
         let box_id = self.boxes_pull.take().await;
+        self.init_box(box_id).await
+    }
+
+    /// Takes `count` boxes at once, so that a test doesn't hold some boxes while waiting for others
+    pub async fn initialize_sandboxes(self: Arc<Self>, count: usize) -> Result<Vec<Sandbox>> {
+        if count > self.sandboxes_count() {
+            bail!(
+                "{count} sandboxes are required, but only {} exist",
+                self.sandboxes_count()
+            );
+        }
+        let mut box_ids = Vec::with_capacity(count);
+        {
+            let _taking = self.group_taking.lock().await;
+            for _ in 0..count {
+                box_ids.push(self.boxes_pull.take().await);
+            }
+        }
+
+        let mut sandboxes = Vec::with_capacity(count);
+        let mut box_ids = box_ids.into_iter();
+        while let Some(box_id) = box_ids.next() {
+            match Arc::clone(&self).init_box(box_id).await {
+                Ok(sandbox) => sandboxes.push(sandbox),
+                Err(e) => {
+                    for box_id in box_ids {
+                        self.boxes_pull.put(box_id);
+                    }
+                    return Err(e);
+                }
+            }
+        }
+        Ok(sandboxes)
+    }
+
+    async fn init_box(self: Arc<Self>, box_id: usize) -> Result<Sandbox> {
         let mut log_state = LogState::new();
         log_state = log_state.push("box", &*format!("{box_id}"));
         log::debug!("({log_state}) starting");
@@ -184,7 +228,7 @@ impl Service {
     }
 }
 
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum RunStatus {
     Ok,
     Tl,
@@ -250,7 +294,8 @@ impl Sandbox {
             .arg(format!("--box-id={}", self.id))
             .arg(format!("--meta={meta_path}"))
             .stdout(Stdio::null())
-            .stderr(Stdio::null());
+            .stderr(Stdio::null())
+            .kill_on_drop(true);
 
         if let Some(input_path) = target.stdin {
             command.arg(format!("--stdin={input_path}"));
@@ -428,6 +473,15 @@ impl Sandbox {
         );
         Ok(tokio::fs::File::open(format!("{}/{from}", self.inner_dir())).await?)
     }
+
+    pub async fn read_string_from_box(&self, from: &str) -> Result<String> {
+        let mut text = String::new();
+        self.read_from_box(from)
+            .await?
+            .read_to_string(&mut text)
+            .await?;
+        Ok(text)
+    }
 }
 
 #[tokio::test]
@@ -1,7 +1,6 @@
 #[cfg(not(feature = "mock"))]
 pub mod websocket;
 
-use crate::VISIBLE_DATA_LEN;
 use crate::prelude::*;
 
 #[allow(dead_code)]
@@ -9,15 +8,51 @@ pub mod income {
     use invoker_auth::Challenge;
     use std::future;
 
-    use crate::short_slice_u8;
+    use crate::{
+        judge::{Lang, api::submission::LimitsOverride},
+        short_slice_u8,
+    };
 
     use super::Result;
 
+    /// Cached problem package to judge solution with
+    #[derive(Debug)]
+    pub struct ProblemRef {
+        pub hash: Box<str>,
+        pub lang: Lang,
+    }
+
     pub enum Msg {
         Challenge(Challenge),
         AuthVerdict(bool),
-        Start { data: Box<[u8]> },
-        Stop,
+        // `data` is solution source if `problem` is set, full task package otherwise
+        Start {
+            submission: Box<str>,
+            problem: Option<ProblemRef>,
+            data: Box<[u8]>,
+        },
+        Problem {
+            hash: Box<str>,
+            data: Box<[u8]>,
+        },
+        // custom invocation, `data` is tar with `solution` source and `stdin`
+        Run {
+            submission: Box<str>,
+            lang: Lang,
+            limits: LimitsOverride,
+            data: Box<[u8]>,
+        },
+        // `data` is tar with target `solution` source and hack `input`
+        Hack {
+            submission: Box<str>,
+            problem: ProblemRef,
+            group: Option<usize>,
+            data: Box<[u8]>,
+        },
+        // stops all submissions if `submission` is not set
+        Stop {
+            submission: Option<Box<str>>,
+        },
         Close,
     }
 
@@ -28,11 +63,49 @@ pub mod income {
                     .debug_struct("Challenge")
                     .field("data", &Box::<[u8]>::from(short_slice_u8(&*challenge)))
                     .finish(),
-                Self::Start { data } => f
+                Self::Start {
+                    submission,
+                    problem,
+                    data,
+                } => f
                     .debug_struct("Start")
+                    .field("submission", submission)
+                    .field("problem", problem)
                     .field("data", &Box::<[u8]>::from(short_slice_u8(&data)))
                     .finish(),
-                Self::Stop => write!(f, "Stop"),
+                Self::Problem { hash, data } => f
+                    .debug_struct("Problem")
+                    .field("hash", hash)
+                    .field("data", &Box::<[u8]>::from(short_slice_u8(&data)))
+                    .finish(),
+                Self::Run {
+                    submission,
+                    lang,
+                    limits,
+                    data,
+                } => f
+                    .debug_struct("Run")
+                    .field("submission", submission)
+                    .field("lang", lang)
+                    .field("limits", limits)
+                    .field("data", &Box::<[u8]>::from(short_slice_u8(&data)))
+                    .finish(),
+                Self::Hack {
+                    submission,
+                    problem,
+                    group,
+                    data,
+                } => f
+                    .debug_struct("Hack")
+                    .field("submission", submission)
+                    .field("problem", problem)
+                    .field("group", group)
+                    .field("data", &Box::<[u8]>::from(short_slice_u8(&data)))
+                    .finish(),
+                Self::Stop { submission } => f
+                    .debug_struct("Stop")
+                    .field("submission", submission)
+                    .finish(),
                 Self::Close => write!(f, "Close"),
                 Self::AuthVerdict(verdict) => {
                     write!(f, "{}", if *verdict { "Approved" } else { "Denied" })
@@ -57,16 +130,25 @@ pub mod outgo {
     use colored::Colorize;
     use invoker_auth::Solution;
 
-    use super::{Result, VISIBLE_DATA_LEN};
-    use crate::judge::api::test::Verdict;
+    use super::Result;
+    use crate::{
+        judge::{
+            api::{compilation, hack, invocation, submission, test::Verdict},
+            payload::{DataState, Encoding},
+        },
+        short_slice_u8,
+    };
 
     #[derive(Debug)]
     pub enum FullVerdict {
         Ok {
-            score: usize,
-            groups_score: Box<[usize]>,
+            score: f64,
+            groups_score: Box<[f64]>,
+            limits: submission::Limits,
+            compilation: Option<compilation::Report>,
+            summary: submission::Summary,
         },
-        Ce(Box<str>),
+        Ce(compilation::Report),
         Te(Box<str>),
     }
 
@@ -77,12 +159,19 @@ pub mod outgo {
             name: Box<str>,
         },
         ChallengeSolution(Solution),
-        FullVerdict(FullVerdict),
+        FullVerdict {
+            submission: Box<str>,
+            verdict: FullVerdict,
+        },
         TestVerdict {
+            submission: Box<str>,
             test_id: usize,
             verdict: Verdict,
+            score: f64,
             time: f64,
             memory: u64,
+            data_state: DataState,
+            encoding: Encoding,
             data: Box<[u8]>,
         },
         Exited {
@@ -90,11 +179,25 @@ pub mod outgo {
             data: Box<str>,
         },
         Error {
+            submission: Option<Box<str>>,
             msg: Box<str>,
         },
         OpError {
+            submission: Box<str>,
             msg: Box<str>,
         },
+        NeedProblem {
+            submission: Box<str>,
+            problem: Box<str>,
+        },
+        RunResult {
+            submission: Box<str>,
+            result: invocation::Result,
+        },
+        HackResult {
+            submission: Box<str>,
+            result: hack::Result,
+        },
     }
 
     impl std::fmt::Debug for Msg {
@@ -107,30 +210,71 @@ pub mod outgo {
                     .finish(),
                 Self::ChallengeSolution(data) => f
                     .debug_struct("ChallengeSolution")
-                    .field("data", &Box::<[u8]>::from(&data[..VISIBLE_DATA_LEN]))
+                    .field("data", &Box::<[u8]>::from(short_slice_u8(&data)))
+                    .finish(),
+                Self::FullVerdict {
+                    submission,
+                    verdict,
+                } => f
+                    .debug_struct("FullVerdict")
+                    .field("submission", submission)
+                    .field("verdict", verdict)
                     .finish(),
-                Self::FullVerdict(verdict) => f.debug_tuple("FullVerdict").field(verdict).finish(),
                 Self::TestVerdict {
+                    submission,
                     test_id,
                     verdict,
+                    score,
                     time,
                     memory,
+                    data_state,
+                    encoding,
                     data,
                 } => f
                     .debug_struct("TestVerdict")
+                    .field("submission", submission)
                     .field("test_id", test_id)
                     .field("verdict", verdict)
+                    .field("score", score)
                     .field("time", time)
                     .field("memory", memory)
-                    .field("data", &Box::<[u8]>::from(&data[..VISIBLE_DATA_LEN]))
+                    .field("data_state", data_state)
+                    .field("encoding", encoding)
+                    .field("data", &Box::<[u8]>::from(short_slice_u8(&data)))
                     .finish(),
                 Self::Exited { code, data } => f
                     .debug_struct("Exited")
                     .field("code", code)
                     .field("data", data)
                     .finish(),
-                Self::Error { msg } => f.debug_struct("Error").field("msg", msg).finish(),
-                Self::OpError { msg } => f.debug_struct("OpError").field("msg", msg).finish(),
+                Self::Error { submission, msg } => f
+                    .debug_struct("Error")
+                    .field("submission", submission)
+                    .field("msg", msg)
+                    .finish(),
+                Self::OpError { submission, msg } => f
+                    .debug_struct("OpError")
+                    .field("submission", submission)
+                    .field("msg", msg)
+                    .finish(),
+                Self::NeedProblem {
+                    submission,
+                    problem,
+                } => f
+                    .debug_struct("NeedProblem")
+                    .field("submission", submission)
+                    .field("problem", problem)
+                    .finish(),
+                Self::RunResult { submission, result } => f
+                    .debug_struct("RunResult")
+                    .field("submission", submission)
+                    .field("result", result)
+                    .finish(),
+                Self::HackResult { submission, result } => f
+                    .debug_struct("HackResult")
+                    .field("submission", submission)
+                    .field("result", result)
+                    .finish(),
             }
         }
     }
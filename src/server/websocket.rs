@@ -4,6 +4,13 @@ use super::{income, outgo};
 
 pub use http::Uri;
 use invoker_auth::Challenge;
+use tar_archive_rs::{self as archive, ArchiveItem};
+
+use crate::judge::api::{
+    compilation, hack, invocation,
+    submission::{self, LimitsOverride},
+    test::Verdict,
+};
 
 const MAX_MESSAGE_SIZE: usize = 1 << 31;
 
@@ -53,7 +60,7 @@ mod raw_msg {
 
                 match key {
                     "DATA" => break Some(buf.into()),
-                    "TPYE" => r#type = Some(value.into()),
+                    "TYPE" => r#type = Some(value.into()),
                     _ => fields.push((key.into(), value.into())),
                 }
             };
@@ -143,6 +150,90 @@ mod raw_msg {
         }
     }
 }
+async fn add_compilation_report(
+    body: &mut raw_msg::Body,
+    report: compilation::Report,
+) -> Result<()> {
+    body.add_fields(vec![
+        (
+            &"COMPILE_EXIT_CODE",
+            &report
+                .exit_code
+                .map(|code| code.to_string())
+                .unwrap_or("-".to_string()),
+        ),
+        (&"COMPILE_TIME", &report.time),
+        (&"COMPILE_MEMORY", &report.memory),
+    ]);
+    let data = archive::pack(&[
+        ArchiveItem {
+            path: "stdout",
+            data: report.stdout.as_bytes(),
+        },
+        ArchiveItem {
+            path: "stderr",
+            data: report.stderr.as_bytes(),
+        },
+    ])
+    .await
+    .map_err(|e| anyhow!("compilation report packing: {e:?}"))?;
+    body.set_data(data);
+    Ok(())
+}
+
+/// Adds `MAX_TIME`, `MAX_MEMORY`, `FAILED_TESTS`, `FAILED_VERDICTS` and `VERDICTS` fields,
+/// groups without failed test are written as `-`
+fn add_summary(body: &mut raw_msg::Body, summary: submission::Summary) {
+    let failures = |f: &dyn Fn(usize, Verdict) -> String| {
+        summary
+            .groups_failure
+            .iter()
+            .map(|failure| match failure {
+                Some((test, verdict)) => f(*test, *verdict),
+                None => "-".to_string(),
+            })
+            .collect::<Vec<_>>()
+            .join(" ")
+    };
+    body.add_fields(vec![
+        (&"MAX_TIME", &summary.max_time),
+        (&"MAX_MEMORY", &summary.max_memory),
+        (&"FAILED_TESTS", &failures(&|test, _| test.to_string())),
+        (
+            &"FAILED_VERDICTS",
+            &failures(&|_, verdict| verdict.to_string()),
+        ),
+        (
+            &"VERDICTS",
+            &summary
+                .verdicts
+                .iter()
+                .map(|(verdict, count)| format!("{verdict}:{count}"))
+                .collect::<Vec<_>>()
+                .join(" "),
+        ),
+    ]);
+}
+
+/// Reads optional `TIME_LIMIT`, `REAL_TIME_LIMIT`, `MEMORY_LIMIT` and `STACK_LIMIT` fields
+fn parse_limits(msg: &raw_msg::Msg) -> Result<LimitsOverride> {
+    fn parse<T: std::str::FromStr>(msg: &raw_msg::Msg, name: &str) -> Result<Option<T>>
+    where
+        T::Err: std::error::Error + Send + Sync + 'static,
+    {
+        msg.field(name)
+            .map(|value| value.parse::<T>())
+            .transpose()
+            .with_context(|| format!("field '{name}'"))
+    }
+    Ok(LimitsOverride {
+        time: parse(msg, "TIME_LIMIT")?,
+        real_time: parse(msg, "REAL_TIME_LIMIT")?,
+        memory: parse(msg, "MEMORY_LIMIT")?,
+        stack: parse(msg, "STACK_LIMIT")?,
+    })
+}
+
 pub struct Service {
     read: Mutex<Receiver<TcpStream, DeflateDecoder>>,
     write: Mutex<Sender<TcpStream, DeflateEncoder>>,
@@ -180,96 +271,240 @@ impl Service {
             read: Mutex::new(read),
         })
     }
+
+    /// Reports a message that can't be attributed to a submission,
+    /// so that the server doesn't wait for its verdict forever
+    async fn reject(&self, msg: &str) -> Result<()> {
+        log::error!("{msg}");
+        outgo::Sender::send(
+            self,
+            outgo::Msg::Error {
+                submission: None,
+                msg: Box::from(msg),
+            },
+        )
+        .await
+    }
 }
 
 impl outgo::Sender for Service {
     async fn send(&self, msg: outgo::Msg) -> Result<()> {
         log::info!("sending: {msg:?}");
+        let body = match msg {
+            outgo::Msg::FullVerdict {
+                submission,
+                verdict,
+            } => {
+                let mut body = raw_msg::Body::new("VERDICT");
+                body.add_field(&"SUBMISSION", &submission);
+                match verdict {
+                    outgo::FullVerdict::Ok {
+                        score,
+                        groups_score,
+                        limits,
+                        compilation,
+                        summary,
+                    } => {
+                        body.add_fields(vec![
+                            (&"NAME", &"OK"),
+                            (&"SUM", &score),
+                            (
+                                &"GROUPS",
+                                &String::from_utf8_lossy(
+                                    &*groups_score
+                                        .into_iter()
+                                        .flat_map(|score| format!("{score} ").into_bytes())
+                                        .collect::<Vec<u8>>(),
+                                ),
+                            ),
+                            (&"TIME_LIMIT", &limits.time),
+                            (&"REAL_TIME_LIMIT", &limits.real_time),
+                            (&"MEMORY_LIMIT", &limits.memory),
+                        ]);
+                        add_summary(&mut body, summary);
+                        if let Some(compilation) = compilation {
+                            add_compilation_report(&mut body, compilation).await?;
+                        }
+                    }
+                    outgo::FullVerdict::Ce(compilation) => {
+                        body.add_fields(vec![(&"NAME", &"CE"), (&"MESSAGE", &compilation.stderr)]);
+                        add_compilation_report(&mut body, compilation).await?;
+                    }
+                    outgo::FullVerdict::Te(msg) => {
+                        body.add_fields(vec![(&"NAME", &"TE"), (&"MESSAGE", &msg)]);
+                    }
+                }
+                body
+            }
+            outgo::Msg::TestVerdict {
+                submission,
+                test_id,
+                verdict,
+                score,
+                time,
+                memory,
+                data_state,
+                encoding,
+                data,
+            } => {
+                let mut body = raw_msg::Body::new("TEST");
+                body.add_fields(vec![
+                    (&"SUBMISSION", &submission),
+                    (&"ID", &test_id),
+                    (&"VERDICT", &verdict),
+                    (&"SCORE", &score),
+                    (&"TIME", &time),
+                    (&"MEMORY", &memory),
+                    (&"DATA_STATE", &data_state),
+                    (&"DATA_ENCODING", &encoding),
+                ])
+                .set_data(data);
+                body
+            }
+            outgo::Msg::Exited { code, data } => {
+                let mut body = raw_msg::Body::new("EXITED");
+                body.add_fields(vec![(&"CODE", &code), (&"MESSAGE", &data)]);
+                body
+            }
+            outgo::Msg::Error { submission, msg } => {
+                let mut body = raw_msg::Body::new("ERROR");
+                if let Some(submission) = submission {
+                    body.add_field(&"SUBMISSION", &submission);
+                }
+                body.add_field(&"MESSAGE", &msg);
+                body
+            }
+            outgo::Msg::OpError { submission, msg } => {
+                let mut body = raw_msg::Body::new("OPERROR");
+                body.add_fields(vec![(&"SUBMISSION", &submission), (&"MESSAGE", &msg)]);
+                body
+            }
+            outgo::Msg::NeedProblem {
+                submission,
+                problem,
+            } => {
+                let mut body = raw_msg::Body::new("NEED_PROBLEM");
+                body.add_fields(vec![(&"SUBMISSION", &submission), (&"PROBLEM", &problem)]);
+                body
+            }
+            outgo::Msg::RunResult { submission, result } => {
+                let mut body = raw_msg::Body::new("RUN_RESULT");
+                body.add_field(&"SUBMISSION", &submission);
+                match result {
+                    invocation::Result::Ok(report) => {
+                        body.add_fields(vec![
+                            (&"NAME", &"OK"),
+                            (&"STATUS", &report.status),
+                            (
+                                &"EXIT_CODE",
+                                &report
+                                    .exit_code
+                                    .map(|code| code.to_string())
+                                    .unwrap_or("-".to_string()),
+                            ),
+                            (&"TIME", &report.time),
+                            (&"MEMORY", &report.memory),
+                        ]);
+                        let data = archive::pack(&[
+                            ArchiveItem {
+                                path: "stdout",
+                                data: report.stdout.as_bytes(),
+                            },
+                            ArchiveItem {
+                                path: "stderr",
+                                data: report.stderr.as_bytes(),
+                            },
+                        ])
+                        .await
+                        .map_err(|e| anyhow!("invocation report packing: {e:?}"))?;
+                        body.set_data(data);
+                    }
+                    invocation::Result::Ce(compilation) => {
+                        body.add_fields(vec![(&"NAME", &"CE"), (&"MESSAGE", &compilation.stderr)]);
+                        add_compilation_report(&mut body, compilation).await?;
+                    }
+                    invocation::Result::Te(msg) => {
+                        body.add_fields(vec![(&"NAME", &"TE"), (&"MESSAGE", &msg)]);
+                    }
+                }
+                body
+            }
+            outgo::Msg::HackResult { submission, result } => {
+                let mut body = raw_msg::Body::new("HACK_RESULT");
+                body.add_field(&"SUBMISSION", &submission);
+                match result {
+                    hack::Result::Ok {
+                        successful,
+                        test,
+                        answer,
+                    } => {
+                        body.add_fields(vec![
+                            (
+                                &"NAME",
+                                &if successful {
+                                    "SUCCESSFUL"
+                                } else {
+                                    "UNSUCCESSFUL"
+                                },
+                            ),
+                            (&"VERDICT", &test.verdict),
+                            (&"SCORE", &test.score),
+                            (&"TIME", &test.time),
+                            (&"MEMORY", &test.memory),
+                        ]);
+                        let mut items = vec![
+                            ArchiveItem {
+                                path: "output",
+                                data: test.output.as_bytes(),
+                            },
+                            ArchiveItem {
+                                path: "message",
+                                data: test.message.as_bytes(),
+                            },
+                            ArchiveItem {
+                                path: "answer",
+                                data: answer.as_bytes(),
+                            },
+                        ];
+                        if let Some(comment) = &test.comment {
+                            items.push(ArchiveItem {
+                                path: "comment",
+                                data: comment.text.as_bytes(),
+                            });
+                        }
+                        let data = archive::pack(&items)
+                            .await
+                            .map_err(|e| anyhow!("hack report packing: {e:?}"))?;
+                        body.set_data(data);
+                    }
+                    hack::Result::Invalid(msg) => {
+                        body.add_fields(vec![(&"NAME", &"INVALID"), (&"MESSAGE", &msg)]);
+                    }
+                    hack::Result::Ce(compilation) => {
+                        body.add_fields(vec![(&"NAME", &"CE"), (&"MESSAGE", &compilation.stderr)]);
+                        add_compilation_report(&mut body, compilation).await?;
+                    }
+                    hack::Result::Te(msg) => {
+                        body.add_fields(vec![(&"NAME", &"TE"), (&"MESSAGE", &msg)]);
+                    }
+                }
+                body
+            }
+            outgo::Msg::Token { token, name } => {
+                let mut body = raw_msg::Body::new("TOKEN");
+                body.add_fields(vec![(&"ID", &token.as_u128()), (&"KEY", &name)]);
+                body
+            }
+            outgo::Msg::ChallengeSolution(data) => {
+                let mut body = raw_msg::Body::new("AUTH");
+                body.set_data(Box::from(&*data));
+                body
+            }
+        };
         self.write
             .lock()
             .await
-            .write(
-                {
-                    let body = match msg {
-                        outgo::Msg::FullVerdict(verdict) => {
-                            let mut body = raw_msg::Body::new("VERDICT");
-                            match verdict {
-                                outgo::FullVerdict::Ok {
-                                    score,
-                                    groups_score,
-                                } => {
-                                    body.add_fields(vec![
-                                        (&"NAME", &"OK"),
-                                        (&"SUM", &score),
-                                        (
-                                            &"GROUPS",
-                                            &String::from_utf8_lossy(
-                                                &*groups_score
-                                                    .into_iter()
-                                                    .flat_map(|score| {
-                                                        format!("{score} ").into_bytes()
-                                                    })
-                                                    .collect::<Vec<u8>>(),
-                                            ),
-                                        ),
-                                    ]);
-                                }
-                                outgo::FullVerdict::Ce(msg) => {
-                                    body.add_fields(vec![(&"NAME", &"CE"), (&"MESSAGE", &msg)]);
-                                }
-                                outgo::FullVerdict::Te(msg) => {
-                                    body.add_fields(vec![(&"NAME", &"TE"), (&"MESSAGE", &msg)]);
-                                }
-                            }
-                            body
-                        }
-                        outgo::Msg::TestVerdict {
-                            test_id,
-                            verdict,
-                            time,
-                            memory,
-                            data,
-                        } => {
-                            let mut body = raw_msg::Body::new("TEST");
-                            body.add_fields(vec![
-                                (&"ID", &test_id),
-                                (&"VERDCIT", &verdict),
-                                (&"TIME", &time),
-                                (&"MEMORY", &memory),
-                            ])
-                            .set_data(data);
-                            body
-                        }
-                        outgo::Msg::Exited { code, data } => {
-                            let mut body = raw_msg::Body::new("EXITED");
-                            body.add_fields(vec![(&"CODE", &code), (&"MESSAGE", &data)]);
-                            body
-                        }
-                        outgo::Msg::Error { msg } => {
-                            let mut body = raw_msg::Body::new("ERROR");
-                            body.add_field(&"MESSAGE", &msg);
-                            body
-                        }
-                        outgo::Msg::OpError { msg } => {
-                            let mut body = raw_msg::Body::new("OPERROR");
-                            body.add_field(&"MESSAGE", &msg);
-                            body
-                        }
-                        outgo::Msg::Token { token, name } => {
-                            let mut body = raw_msg::Body::new("TOKEN");
-                            body.add_fields(vec![(&"ID", &token.as_u128()), (&"KEY", &name)]);
-                            body
-                        }
-                        outgo::Msg::ChallengeSolution(data) => {
-                            let mut body = raw_msg::Body::new("AUTH");
-                            body.set_data(Box::from(&*data));
-                            body
-                        }
-                    };
-                    body.into_bytes()
-                },
-                ratchet_rs::PayloadType::Binary,
-            )
+            .write(body.into_bytes(), ratchet_rs::PayloadType::Binary)
             .await
             .context("websocket message sending")?;
         Ok(())
@@ -303,15 +538,133 @@ impl income::Receiver for Service {
                     income::Msg::Challenge(Challenge::from(&*data))
                 }
                 "START" => {
+                    let Some(submission) = msg.field("SUBMISSION") else {
+                        self.reject(&format!("{}: field 'SUBMISSION' not found", msg.r#type()))
+                            .await?;
+                        continue;
+                    };
                     let Some(data) = msg.data() else {
                         log::error!("data not found");
                         continue;
                     };
+                    let problem = match (msg.field("PROBLEM"), msg.field("LANG")) {
+                        (Some(hash), Some(lang)) => match lang.parse() {
+                            Ok(lang) => Some(income::ProblemRef {
+                                hash: Box::from(hash),
+                                lang,
+                            }),
+                            Err(err) => {
+                                log::error!("parsing 'LANG': {err}");
+                                continue;
+                            }
+                        },
+                        (Some(_), None) => {
+                            log::error!("field 'LANG' not found");
+                            continue;
+                        }
+                        (None, _) => None,
+                    };
                     income::Msg::Start {
+                        submission: Box::from(submission),
+                        problem,
+                        data: Box::from(data),
+                    }
+                }
+                "RUN" => {
+                    let Some(submission) = msg.field("SUBMISSION") else {
+                        self.reject(&format!("{}: field 'SUBMISSION' not found", msg.r#type()))
+                            .await?;
+                        continue;
+                    };
+                    let lang = match msg.field("LANG").map(str::parse) {
+                        Some(Ok(lang)) => lang,
+                        Some(Err(err)) => {
+                            log::error!("parsing 'LANG': {err}");
+                            continue;
+                        }
+                        None => {
+                            log::error!("field 'LANG' not found");
+                            continue;
+                        }
+                    };
+                    let limits = match parse_limits(&msg) {
+                        Ok(limits) => limits,
+                        Err(err) => {
+                            log::error!("parsing limits: {err}");
+                            continue;
+                        }
+                    };
+                    let Some(data) = msg.data() else {
+                        log::error!("data not found");
+                        continue;
+                    };
+                    income::Msg::Run {
+                        submission: Box::from(submission),
+                        lang,
+                        limits,
+                        data: Box::from(data),
+                    }
+                }
+                "HACK" => {
+                    let Some(submission) = msg.field("SUBMISSION") else {
+                        self.reject(&format!("{}: field 'SUBMISSION' not found", msg.r#type()))
+                            .await?;
+                        continue;
+                    };
+                    let Some(hash) = msg.field("PROBLEM") else {
+                        log::error!("field 'PROBLEM' not found");
+                        continue;
+                    };
+                    let lang = match msg.field("LANG").map(str::parse) {
+                        Some(Ok(lang)) => lang,
+                        Some(Err(err)) => {
+                            log::error!("parsing 'LANG': {err}");
+                            continue;
+                        }
+                        None => {
+                            log::error!("field 'LANG' not found");
+                            continue;
+                        }
+                    };
+                    let group = match msg.field("GROUP").map(str::parse) {
+                        Some(Ok(group)) => Some(group),
+                        Some(Err(err)) => {
+                            log::error!("parsing 'GROUP': {err}");
+                            continue;
+                        }
+                        None => None,
+                    };
+                    let Some(data) = msg.data() else {
+                        log::error!("data not found");
+                        continue;
+                    };
+                    income::Msg::Hack {
+                        submission: Box::from(submission),
+                        problem: income::ProblemRef {
+                            hash: Box::from(hash),
+                            lang,
+                        },
+                        group,
+                        data: Box::from(data),
+                    }
+                }
+                "PROBLEM" => {
+                    let Some(hash) = msg.field("HASH") else {
+                        log::error!("field 'HASH' not found");
+                        continue;
+                    };
+                    let Some(data) = msg.data() else {
+                        log::error!("data not found");
+                        continue;
+                    };
+                    income::Msg::Problem {
+                        hash: Box::from(hash),
                         data: Box::from(data),
                     }
                 }
-                "STOP" => income::Msg::Stop,
+                "STOP" => income::Msg::Stop {
+                    submission: msg.field("SUBMISSION").map(Box::from),
+                },
                 "CLOSE" => income::Msg::Close,
                 command => {
                     log::error!("incomming websocket message: incorrect command: {command}");